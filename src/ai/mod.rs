@@ -18,39 +18,70 @@
  */
 extern crate rand;
 
-use super::incoming::{Event, Team, TeamNoPosNoHp, Bot};
+use super::incoming::{Event, Team, TeamNoPosNoHp, Bot, StartMessage, EndMessage};
 use super::{Position, GameConfig};
-
-use self::rand::{thread_rng, Rng};
+use super::board::Board;
+use super::hexset::HexSet;
+use super::pathfinding;
+use super::tracker::Tracker;
+
+use self::rand::{thread_rng, Rng, SeedableRng, XorShiftRng};
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
 use std::default::Default;
+use std::fmt;
+
+#[cfg(test)] use test::Bencher;
 
 pub trait Ai {
+    /// Called when the server has assigned us a team, before the game starts.
+    fn on_connected(&mut self, _team_id: u32, _config: GameConfig) {}
+    /// Called once per game with the config, our team and the enemy roster.
+    fn on_game_start(&mut self, _start: StartMessage) {}
+    /// Called at the start of every round, before `set_state` and `respond`.
+    fn on_round(&mut self, _round_id: u32) {}
+    /// Called when the game is over, `end.winner_team_id` tells who won.
+    fn on_game_end(&mut self, _end: EndMessage) {}
+    /// Called with the actions that were actually sent for the round. They
+    /// differ from what `respond` returned when validation repaired them or
+    /// the watchdog used its fallback.
+    fn on_actions_sent(&mut self, _round_id: u32, _actions: &[Action]) {}
+    /// Turns the AI's progress output on or off. The simulator turns it off.
+    fn set_verbose(&mut self, _verbose: bool) {}
     fn respond(&mut self, Vec<Event>) -> Vec<Action>;
     fn set_state(&mut self, config: GameConfig, you: Team, other_teamss: Vec<TeamNoPosNoHp>) -> ();
     fn get_bot_by_id(&mut self, bot_id:u32) -> Option<&Bot>;
     fn is_on_playing_field(&self, pos: &Position) -> bool;
 }
 
-#[derive(Debug, Default, Clone)]
-struct MapTile {
-    pos: Position,
-    asteroid: bool
-}
-
-impl PartialEq for MapTile {
-    fn eq(&self, other: &MapTile) -> bool {
-        self.pos == other.pos
-    }
-}
-
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct State {
     //global state
     last_target: Option<Position>,
-    asteroid_map: Vec<MapTile>,
-    found_asteroids: i32,
+    // hexes our bots have seen, with or without an asteroid
+    seen: HexSet,
+    asteroids: HexSet,
     scan_away: bool,
-    shoot_count: i32
+    shoot_count: i32,
+    // where the enemies probably are, and what we did last round to find out
+    enemies: Tracker,
+    radars: Vec<Position>,
+    shots: Vec<(u32, Position)>
+}
+
+impl State {
+    fn new(board: &Board, move_: u32) -> State {
+        State {
+            last_target: None,
+            seen: HexSet::for_board(board),
+            asteroids: HexSet::for_board(board),
+            scan_away: false,
+            shoot_count: 0,
+            enemies: Tracker::new(board, move_, 0),
+            radars: Vec::new(),
+            shots: Vec::new()
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -58,36 +89,57 @@ struct BotState{
     bot_id: u32,
 }
 
-#[derive(Default)]
+/// The random number generator every `Ai` gets. All randomness has to come
+/// from it, so that the same seed and events always give the same actions.
+pub type AiRng = XorShiftRng;
+
+pub fn seeded_rng(seed: u64) -> AiRng {
+    // XorShiftRng can't be seeded with all zeroes, the constants keep it from that
+    XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x2545F491, 0x9E3779B9])
+}
+
+pub fn random_seed() -> u64 {
+    thread_rng().gen()
+}
+
 struct RandomAi {
     config: GameConfig,
     you: Team,
     other_teams: Vec<TeamNoPosNoHp>,
-    current_state: State
+    current_state: State,
+    rng: AiRng,
+    board: Board,
+    scan_tolerance: f32,
+    max_shoot_count: i32,
+    track_confidence: f64,
+    shoot_deltas: Vec<Position>,
+    verbose: bool
 }
 
-fn filter_asteroids(positions: Vec<Position>, asteroids: Vec<MapTile>) -> Vec<Position> {
-    positions.into_iter().filter(|pos| !asteroids.contains(&MapTile { pos: *pos, asteroid: true })).collect()
-}
-
-fn get_move_position(bot: &Bot, move_: u32, see: i32, field_radius: i32, asteroid_map: Vec<MapTile>, other_bots: Vec<Bot>) -> Position {
-    let allowed_positions = bot.pos.positions_at(move_, field_radius);
-    let asteroids: Vec<MapTile> = asteroid_map.clone().into_iter().filter(|tile| tile.asteroid).collect();
-    let allowed_free_positions = filter_asteroids(allowed_positions.clone(), asteroids.clone());
-    let positions_without_others: Vec<Position> = allowed_free_positions.clone().into_iter().filter(|pos| {
-              other_bots.iter().fold(true, |memo, other| {
-              pos.distance(other.pos) > see
-      })
-    }).collect();
-    let mut final_positions = allowed_positions.clone();
-    if positions_without_others.len() > 0 {
-          final_positions = positions_without_others.clone();
-    }
-    *thread_rng().choose(&final_positions).unwrap()
+/// A random hex the bot can walk to around the known asteroids, as far as its
+/// move allows and preferably out of sight of our other bots.
+fn get_move_position<R: Rng>(bot: &Bot, move_: u32, see: i32, board: &Board, asteroids: &HexSet, other_bots: Vec<Bot>, rng: &mut R) -> Position {
+    let distances = pathfinding::distances(board, asteroids, bot.pos, move_);
+    let reachable = distances.keys().positions();
+    let furthest = reachable.iter().map(|pos| *distances.get(*pos).unwrap()).max().unwrap_or(0);
+    let allowed_positions: Vec<Position> = reachable.into_iter()
+        .filter(|pos| *distances.get(*pos).unwrap() == furthest)
+        .collect();
+    let positions_without_others: Vec<Position> = allowed_positions.iter().cloned()
+        .filter(|pos| other_bots.iter().all(|other| pos.distance(other.pos) > see))
+        .collect();
+    let final_positions = if positions_without_others.is_empty() { allowed_positions } else { positions_without_others };
+    rng.choose(&final_positions).cloned().unwrap_or(bot.pos)
 }
 
 impl Ai for RandomAi {
 
+    fn on_game_start(&mut self, start: StartMessage) {
+        // asteroids and targets from a previous game are of no use anymore
+        self.set_state(start.config, start.you, start.other_teams);
+        self.current_state = State::new(&self.board, self.config.move_);
+    }
+
     #[allow(unused_variables, unused_assignments)]
     fn respond(&mut self, events: Vec<Event>) -> Vec<Action>  {
 
@@ -96,7 +148,18 @@ impl Ai for RandomAi {
         let mut spotter_bot_id: Option<u32> = None;
         let mut shoot_count = self.current_state.shoot_count;
         let mut bots_to_dodge = Vec::new();
-        let scan_tolerance = 50 as f32;
+        let scan_tolerance = self.scan_tolerance;
+        let mut hit_by: Vec<u32> = Vec::new();
+
+        {
+            let alive_enemies = self.other_teams.iter()
+                .flat_map(|team| team.bots.iter())
+                .filter(|bot| bot.alive)
+                .count();
+            let tracker = &mut self.current_state.enemies;
+            tracker.predict();
+            tracker.set_enemies(alive_enemies as u32);
+        }
 
         for event in events.into_iter()
         {
@@ -107,14 +170,18 @@ impl Ai for RandomAi {
                     bots_to_dodge.push(de.bot_id);
                 },
                 Event::HitEvent(he) => { //hit another ship
+                     if self.you.bots.iter().all(|bot| bot.bot_id != he.bot_id) {
+                         hit_by.push(he.source);
+                     }
                      acquired_target = match self.current_state.last_target{
                          Some(ref tar) => Some(Position {x: tar.x, y:tar.y}),
                          None => None
                      };
                      //println!("Bot ID: {} hit enemy bot: {}", he.source, he.bot_id);
                 },
-                Event::DieEvent(de) => println!("Bot ID: {} died.", de.bot_id),
+                Event::DieEvent(de) => if self.verbose { println!("Bot ID: {} died.", de.bot_id) },
                 Event::SeeEvent(se) =>{
+                    self.current_state.enemies.spotted(se.pos);
                     spotter_bot_id = Some(se.source);
                     acquired_target = Some(Position{x: se.pos.x, y: se.pos.y});
                     shoot_count = 0;
@@ -128,14 +195,13 @@ impl Ai for RandomAi {
                     //se.source, se.pos.x, se.pos.y)
                 },
                 Event::RadarEchoEvent(ree) => {
+                    self.current_state.enemies.spotted(ree.pos);
                     if acquired_target == None {
                         acquired_target = Some(Position { x: ree.pos.x, y: ree.pos.y});
 
                         let tar = acquired_target.unwrap();
-                        if self.current_state.asteroid_map.contains(&MapTile { pos: tar, asteroid: true}) {
-                            let index = self.current_state.asteroid_map.position_elem(&MapTile { pos: tar, asteroid: true}).unwrap();
-                            let tile = self.current_state.asteroid_map.get(index).unwrap();
-                            if !tile.asteroid {
+                        if self.current_state.seen.contains(tar) {
+                            if !self.current_state.asteroids.contains(tar) {
                                     self.current_state.last_target = Some(Position {x:tar.x, y:tar.y});
                                     //println!("Shooting at a non-asteroid");
                             }
@@ -161,67 +227,76 @@ impl Ai for RandomAi {
                 Event::MoveEvent(me) => {}, //println!("Bot ID {} moved to x:{}, y:{}", me.bot_id,
                     //me.pos.x, me.pos.y),
                 Event::SeeAsteroidEvent(sae) => {
-                    match self.current_state.asteroid_map.contains(&MapTile { pos: sae.pos, asteroid: true }) {
-                        true => {},
-                        false => {
-                            self.current_state.asteroid_map.push(MapTile { pos: sae.pos, asteroid: true });
-                            self.current_state.found_asteroids = self.current_state.found_asteroids + 1;
-                            // println!("Asteroid stored at x:{}, y:{}. Asteroids saved {}/{:?}",
-                            //     sae.pos.x,
-                            //     sae.pos.y,
-                            //     self.current_state.asteroid_map.len(),
-                            //     self.config.asteroids
-                            // );
-                        }
-                    }
+                    self.current_state.seen.insert(sae.pos);
+                    self.current_state.asteroids.insert(sae.pos);
+                    self.current_state.enemies.add_asteroid(sae.pos);
+                },
+                // the session lists unknown kinds once per game
+                Event::Unknown { .. } => {}
+            }
+        }
+
+        // Whatever our radars, bots and cannons didn't find isn't there
+        {
+            let config = &self.config;
+            let state = &mut self.current_state;
+            for radar in state.radars.iter() {
+                state.enemies.clear_area(*radar, config.radar as u32);
+            }
+            for bot in self.you.bots.iter().filter(|bot| bot.alive) {
+                state.enemies.clear_area(bot.pos, config.see as u32);
+            }
+            for &(bot_id, target) in state.shots.iter() {
+                if hit_by.contains(&bot_id) {
+                    state.enemies.hit_area(target, config.cannon as u32);
+                } else {
+                    state.enemies.clear_area(target, config.cannon as u32);
                 }
             }
         }
 
-        let shoot_deltas = vec![
-            Position { x:  1, y:  0 },
-            Position { x:  1, y: -1 },
-            Position { x: -1, y:  0 },
-            Position { x: -1, y:  1 },
-            Position { x:  0, y:  1 },
-            Position { x:  0, y: -1 },
-        ];
+        // Nothing fresh to shoot at, but the enemy we lost track of may still be near
+        if acquired_target == None {
+            if let Some((pos, expected)) = self.current_state.enemies.best_target(self.config.cannon as u32) {
+                if expected >= self.track_confidence {
+                    acquired_target = Some(pos);
+                }
+            }
+        }
+
+        let shoot_deltas = self.shoot_deltas.clone();
 
         let botpositions: Vec<Position> = self.you.bots.iter().map(|bot| bot.pos).collect();
 
         let living_bots = self.you.bots.iter().filter(|bot| bot.alive);
 
-        let tile_count = Position { x:0, y:0 }.positions_within(self.config.field_radius as u32).len();
+        let tile_count = self.board.len();
 
-        // Save the asteroid state for each tile
+        // Every tile a bot can see is now known, asteroids were added above
         for bot in self.you.bots.iter().filter(|bot| bot.alive) {
-            //println!("Bot visibility {:?}", bot.pos.positions_within(self.config.see as u32));
             for hex in bot.pos.positions_within(self.config.see as u32) {
-                match self.current_state.asteroid_map.contains(&MapTile { pos: hex, asteroid: true }) {
-                    true => {},
-                    false => {
-                        // Asteroids added already above, if a tile is missing, add asteroid is false
-                        self.current_state.asteroid_map.push(MapTile { pos: hex, asteroid: false });
-                    }
-                }
+                self.current_state.seen.insert(hex);
             }
         }
 
-        let current_map_coverage = (self.current_state.asteroid_map.len() as f32 / tile_count as f32) * 100 as f32;
+        let found_asteroids = self.current_state.asteroids.len() as i32;
+        let current_map_coverage = (self.current_state.seen.len() as f32 / tile_count as f32) * 100 as f32;
         let enough_coverage = current_map_coverage > scan_tolerance;
-        let enough_asteroids = self.current_state.found_asteroids == self.config.asteroids.unwrap();
+        let enough_asteroids = found_asteroids == self.config.asteroids.unwrap();
         if enough_coverage || enough_asteroids {
             self.current_state.scan_away = true;
         }
-         println!("{}% of map tiles stored, {}/{} asteroids, ready for scanning {}",
-                         current_map_coverage,
-                         self.current_state.found_asteroids,
-                         self.config.asteroids.unwrap(),
-                         self.current_state.scan_away);
+        if self.verbose {
+            println!("{}% of map tiles stored, {}/{} asteroids, ready for scanning {}",
+                     current_map_coverage,
+                     found_asteroids,
+                     self.config.asteroids.unwrap(),
+                     self.current_state.scan_away);
+        }
 
         match (move_next, acquired_target) {
             (false, Some(_)) => {
-                if shoot_count < 2 {
+                if shoot_count < self.max_shoot_count {
                     shoot_count += 1
                 }
             },
@@ -231,124 +306,160 @@ impl Ai for RandomAi {
 
         let mut move_bot: bool = false;
 
-        let skip: usize = (thread_rng().next_u32() % 6) as usize;
-        let mut current_radars: Vec<Position> = Vec::new();
-        let actions = living_bots.zip(shoot_deltas.iter().cycle().skip(skip)).map(|(bot, delta)| {
-            let other_bots: Vec<Bot> = self.you.bots.clone().into_iter().filter(|a_bot| a_bot.bot_id != bot.bot_id).collect();
-            move_bot = bots_to_dodge.iter().filter(|&&b| b == bot.bot_id).count() > 0;
-            match (move_bot, acquired_target) {
-                // Someone has shot or scanned us
-                (true, _) => {
-                    let chosen = get_move_position(bot,
-                        self.config.move_,
-                        self.config.see,
-                        self.config.field_radius,
-                        self.current_state.asteroid_map.clone(),
-                        other_bots);
-
-                    Action::MoveAction(MoveAction {
-                                        bot_id: bot.bot_id,
-                                        pos: Position { x: chosen.x, y: chosen. y}
-                    })
-                },
-                // We're safe and have a valid target
-                (false, Some(ref tgtpos)) => {
-                    match spotter_bot_id {
-                        Some(sbot_id) if sbot_id == bot.bot_id => {
-                            let topos = bot.pos.move_away_from(tgtpos, self.config.move_);
-                            Action::MoveAction(MoveAction {
-                                bot_id: bot.bot_id,
-                                pos: topos
-                            })
-                        },
-                        _ => {
-                            let mut cannonpos = Position {
-                                x: tgtpos.x + (delta.x * shoot_count),
-                                y: tgtpos.y + (delta.y * shoot_count)
-                            };
-                            let mut bailout_move = false;
-                            while cannonpos.contains_any_within(botpositions.clone(), self.config.cannon) {
-                                //println!("Moving cannonpos {:?} to avoid hit", cannonpos);
-                                if cannonpos.x == tgtpos.x && cannonpos.y == tgtpos.y {
-                                    bailout_move = true;
-                                    break;
+        let actions: Vec<Action> = {
+            // the closure below can't borrow all of self while the rng is borrowed mutably
+            let config = &self.config;
+            let state = &self.current_state;
+            let you = &self.you;
+            let board = &self.board;
+            // shots fly over asteroids, nothing blocks the aim on its way to the target
+            let open_field = HexSet::for_board(board);
+            let rng = &mut self.rng;
+            let skip: usize = (rng.next_u32() % 6) as usize;
+            let mut current_radars: Vec<Position> = Vec::new();
+            living_bots.zip(shoot_deltas.iter().cycle().skip(skip)).map(|(bot, delta)| {
+                let other_bots: Vec<Bot> = you.bots.clone().into_iter().filter(|a_bot| a_bot.bot_id != bot.bot_id).collect();
+                move_bot = bots_to_dodge.iter().filter(|&&b| b == bot.bot_id).count() > 0;
+                match (move_bot, acquired_target) {
+                    // Someone has shot or scanned us
+                    (true, _) => {
+                        let chosen = get_move_position(bot,
+                            config.move_,
+                            config.see,
+                            board,
+                            &state.asteroids,
+                            other_bots,
+                            &mut *rng);
+
+                        Action::MoveAction(MoveAction {
+                                            bot_id: bot.bot_id,
+                                            pos: Position { x: chosen.x, y: chosen. y}
+                        })
+                    },
+                    // We're safe and have a valid target
+                    (false, Some(ref tgtpos)) => {
+                        match spotter_bot_id {
+                            Some(sbot_id) if sbot_id == bot.bot_id => {
+                                let topos = pathfinding::move_away_from(board, &state.asteroids, bot.pos, *tgtpos, config.move_);
+                                Action::MoveAction(MoveAction {
+                                    bot_id: bot.bot_id,
+                                    pos: topos
+                                })
+                            },
+                            _ => {
+                                let mut cannonpos = board.nearest(Position {
+                                    x: tgtpos.x + (delta.x * shoot_count),
+                                    y: tgtpos.y + (delta.y * shoot_count)
+                                });
+                                let mut bailout_move = false;
+                                while cannonpos.contains_any_within(botpositions.clone(), config.cannon) {
+                                    //println!("Moving cannonpos {:?} to avoid hit", cannonpos);
+                                    if cannonpos.x == tgtpos.x && cannonpos.y == tgtpos.y {
+                                        bailout_move = true;
+                                        break;
+                                    }
+                                    let next = pathfinding::move_towards(board, &open_field, cannonpos, *tgtpos, 1);
+                                    if next == cannonpos {
+                                        bailout_move = true;
+                                        break;
+                                    }
+                                    cannonpos = next;
+                                    //println!("Moved to {:?}", cannonpos);
                                 }
-                                cannonpos = cannonpos.move_towards(*tgtpos, 1);
-                                //println!("Moved to {:?}", cannonpos);
-                            }
-                            match bailout_move {
-                                true => {
-                                    let chosen = get_move_position(bot,
-                                        self.config.move_,
-                                        self.config.see,
-                                        self.config.field_radius,
-                                            self.current_state.asteroid_map.clone(),
-                                        other_bots);
-
-                                    Action::MoveAction(MoveAction {
-                                                        bot_id: bot.bot_id,
-                                                        pos: Position { x: chosen.x, y: chosen. y}
-                                    })
-                                },
-                                false => {
-                                    Action::CannonAction(CannonAction {
-                                       bot_id: bot.bot_id,
-                                        pos: cannonpos
-                                    })
+                                match bailout_move {
+                                    true => {
+                                        let chosen = get_move_position(bot,
+                                            config.move_,
+                                            config.see,
+                                            board,
+                                            &state.asteroids,
+                                            other_bots,
+                                            &mut *rng);
+
+                                        Action::MoveAction(MoveAction {
+                                                            bot_id: bot.bot_id,
+                                                            pos: Position { x: chosen.x, y: chosen. y}
+                                        })
+                                    },
+                                    false => {
+                                        Action::CannonAction(CannonAction {
+                                           bot_id: bot.bot_id,
+                                            pos: cannonpos
+                                        })
+                                    }
                                 }
                             }
                         }
-                    }
-                },
-                // Seek for enemies
-                (false, None) => {
-                    if self.current_state.scan_away && thread_rng().gen_range(1,101) as i32 > (100 - current_map_coverage as i32) {
-                        let mut radar_center = *thread_rng()
-                            .choose(&Position { x: 0, y: 0 }
-                                .positions_within((self.config.field_radius - self.config.radar) as u32)).unwrap();
-
-                        while true {
-                            if current_radars.iter().fold(true, |memo, pos| {
-                                memo && radar_center.distance(*pos) > (self.config.radar * 2)
-
-                            }) {
-                                break;
+                    },
+                    // Seek for enemies
+                    (false, None) => {
+                        let radar_center = if state.scan_away && rng.gen_range(1,101) as i32 > (100 - current_map_coverage as i32) {
+                            // radars of the same round shouldn't overlap, on a small field
+                            // there may be no room left for another one
+                            let centers: Vec<Position> = Position { x: 0, y: 0 }
+                                .positions_within(max(config.field_radius - config.radar, 0) as u32).into_iter()
+                                .filter(|center| current_radars.iter().all(|pos| center.distance(*pos) > config.radar * 2))
+                                .collect();
+                            rng.choose(&centers).cloned()
+                        } else {
+                            None
+                        };
+                        match radar_center {
+                            Some(radar_center) => {
+                                current_radars.push(radar_center);
+                                Action::RadarAction(RadarAction {
+                                    bot_id: bot.bot_id,
+                                    pos: radar_center
+                                })
+                            },
+                            None => {
+                                let chosen = get_move_position(bot,
+                                    config.move_,
+                                    config.see,
+                                    board,
+                                    &state.asteroids,
+                                    other_bots,
+                                    &mut *rng);
+                                Action::MoveAction(MoveAction {
+                                    bot_id: bot.bot_id,
+                                    pos: Position {
+                                        x: chosen.x,
+                                        y: chosen.y
+                                    }
+                                })
                             }
-                            radar_center = *thread_rng()
-                                .choose(&Position { x: 0, y: 0 }
-                                    .positions_within((self.config.field_radius - self.config.radar) as u32)).unwrap();
                         }
-                        current_radars.push(radar_center);
-                        Action::RadarAction(RadarAction {
-                            bot_id: bot.bot_id,
-                            pos: radar_center
-                        })
-                    }
-                    else {
-                        let chosen = get_move_position(bot,
-                            self.config.move_,
-                            self.config.see,
-                            self.config.field_radius,
-                                self.current_state.asteroid_map.clone(),
-                            other_bots);
-                        Action::MoveAction(MoveAction {
-                            bot_id: bot.bot_id,
-                            pos: Position {
-                                x: chosen.x,
-                                y: chosen.y
-                            }
-                        })
                     }
                 }
-            }
 
-        }).collect();
+            }).collect()
+        };
 
         self.current_state.shoot_count = shoot_count;
         actions
     }
 
+    fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    fn on_actions_sent(&mut self, _round_id: u32, actions: &[Action]) {
+        // the tracker learns from where we really scanned and shot
+        self.current_state.radars = actions.iter().filter_map(|action| match *action {
+            Action::RadarAction(ref radar) => Some(radar.pos),
+            _ => None
+        }).collect();
+        self.current_state.shots = actions.iter().filter_map(|action| match *action {
+            Action::CannonAction(ref cannon) => Some((cannon.bot_id, cannon.pos)),
+            _ => None
+        }).collect();
+    }
+
     fn set_state(&mut self, config: GameConfig, you: Team, other_teams: Vec<TeamNoPosNoHp>) {
+        if self.board.radius() != config.field_radius {
+            self.board = Board::new(&config);
+            self.current_state = State::new(&self.board, config.move_);
+        }
         self.config = config;
         self.you = you;
         self.other_teams = other_teams;
@@ -362,43 +473,400 @@ impl Ai for RandomAi {
     }
 
     fn is_on_playing_field(&self, pos: &Position) -> bool {
-        pos.distance(Position { x: 0, y: 0 }) <= self.config.field_radius
+        self.board.contains(*pos)
+    }
+}
+
+/// The type of value an AI parameter takes.
+#[derive(Debug)]
+pub enum ParamKind {
+    Float,
+    Integer,
+    Choice(&'static [&'static str])
+}
+
+/// A tunable value an AI declares, with the value it uses when not told otherwise.
+#[derive(Debug)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub default: &'static str,
+    pub description: &'static str,
+    /// The smallest and largest values allowed for a number
+    pub min: Option<f64>,
+    pub max: Option<f64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Float(f64),
+    Integer(i64),
+    Choice(String)
+}
+
+/// The values of all of an AI's parameters, checked against its `ParamSpec`s.
+#[derive(Debug, Clone)]
+pub struct Params {
+    values: BTreeMap<String, ParamValue>
+}
+
+impl Params {
+    pub fn float(&self, name: &str) -> f64 {
+        match self.values.get(name) {
+            Some(&ParamValue::Float(value)) => value,
+            _ => panic!("{} is not a float parameter", name)
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> i64 {
+        match self.values.get(name) {
+            Some(&ParamValue::Integer(value)) => value,
+            _ => panic!("{} is not an integer parameter", name)
+        }
+    }
+
+    pub fn choice(&self, name: &str) -> &str {
+        match self.values.get(name) {
+            Some(&ParamValue::Choice(ref value)) => value,
+            _ => panic!("{} is not a choice parameter", name)
+        }
+    }
+}
+
+/// Everything that can be wrong with a parameter given on the command line
+/// or in a config file.
+#[derive(Debug)]
+pub enum ParamError {
+    Malformed(String),
+    Unknown { ai: &'static str, name: String },
+    WrongType { name: &'static str, value: String },
+    OutOfRange { name: &'static str, value: String, min: Option<f64>, max: Option<f64> },
+    NotAChoice { name: &'static str, value: String, choices: &'static [&'static str] }
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParamError::Malformed(ref opt) =>
+                write!(f, "{} should look like key=value", opt),
+            ParamError::Unknown { ai, ref name } =>
+                write!(f, "{} has no parameter {}, see --list-ais", ai, name),
+            ParamError::WrongType { name, ref value } =>
+                write!(f, "{} isn't a valid value for {}", value, name),
+            ParamError::OutOfRange { name, ref value, min, max } => {
+                let bounds = match (min, max) {
+                    (Some(min), Some(max)) => format!("between {} and {}", min, max),
+                    (Some(min), None) => format!("at least {}", min),
+                    (None, Some(max)) => format!("at most {}", max),
+                    (None, None) => "something else".to_string()
+                };
+                write!(f, "{} is out of range for {}, it should be {}", value, name, bounds)
+            },
+            ParamError::NotAChoice { name, ref value, choices } =>
+                write!(f, "{} isn't a valid value for {}, use one of {}", value, name, choices.connect(", "))
+        }
     }
 }
 
-pub fn from_name(name: String) -> Box<Ai> {
-    match name.as_ref() {
-        "random" => Box::new(RandomAi { ..Default::default() }),
-        _ => panic!("Can't find an AI with name: {}", name)
+fn in_range(spec: &ParamSpec, number: f64, value: &str) -> Result<(), ParamError> {
+    if spec.min.map_or(false, |min| number < min) || spec.max.map_or(false, |max| number > max) {
+        return Err(ParamError::OutOfRange { name: spec.name, value: value.to_string(), min: spec.min, max: spec.max });
+    }
+    Ok(())
+}
+
+fn parse_param(spec: &ParamSpec, value: &str) -> Result<ParamValue, ParamError> {
+    let wrong_type = || ParamError::WrongType { name: spec.name, value: value.to_string() };
+    match spec.kind {
+        ParamKind::Float => {
+            let number: f64 = try!(value.parse().map_err(|_| wrong_type()));
+            try!(in_range(spec, number, value));
+            Ok(ParamValue::Float(number))
+        },
+        ParamKind::Integer => {
+            let number: i64 = try!(value.parse().map_err(|_| wrong_type()));
+            try!(in_range(spec, number as f64, value));
+            Ok(ParamValue::Integer(number))
+        },
+        ParamKind::Choice(choices) => {
+            if choices.contains(&value) {
+                Ok(ParamValue::Choice(value.to_string()))
+            }
+            else {
+                Err(ParamError::NotAChoice { name: spec.name, value: value.to_string(), choices: choices })
+            }
+        }
+    }
+}
+
+/// Splits an `--ai-opt` value into its key and value.
+pub fn split_opt(opt: &str) -> Result<(String, String), ParamError> {
+    let parts: Vec<&str> = opt.splitn(2, '=').collect();
+    if parts.len() != 2 || parts[0].trim().is_empty() {
+        return Err(ParamError::Malformed(opt.to_string()));
+    }
+    Ok((parts[0].trim().to_string(), parts[1].trim().to_string()))
+}
+
+/// An AI that can be picked with `--ai`.
+pub struct AiEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: &'static [ParamSpec],
+    pub build: fn(AiRng, &Params) -> Box<Ai + Send>
+}
+
+impl AiEntry {
+    /// The declared defaults with `opts` applied over them in order.
+    pub fn params(&self, opts: &[(String, String)]) -> Result<Params, ParamError> {
+        let mut values = BTreeMap::new();
+        for spec in self.params.iter() {
+            let value = parse_param(spec, spec.default).unwrap_or_else(|e| panic!("Bad default in {}: {}", self.name, e));
+            values.insert(spec.name.to_string(), value);
+        }
+        for &(ref name, ref value) in opts.iter() {
+            let spec = match self.params.iter().find(|spec| spec.name == *name) {
+                Some(spec) => spec,
+                None => return Err(ParamError::Unknown { ai: self.name, name: name.clone() })
+            };
+            values.insert(name.clone(), try!(parse_param(spec, value)));
+        }
+        Ok(Params { values: values })
+    }
+
+    pub fn default_params(&self) -> Params {
+        self.params(&[]).unwrap()
+    }
+}
+
+fn build_random(rng: AiRng, params: &Params) -> Box<Ai + Send> {
+    use super::hex::Direction::*;
+    let shoot_deltas = match params.choice("shoot_pattern") {
+        "target" => vec![Position { x: 0, y: 0 }],
+        _ => [East, NorthEast, West, SouthWest, SouthEast, NorthWest].iter().map(|d| d.delta()).collect()
+    };
+    Box::new(RandomAi {
+        config: Default::default(),
+        you: Default::default(),
+        other_teams: Vec::new(),
+        current_state: State::new(&Board::with_radius(0), 0),
+        rng: rng,
+        board: Board::with_radius(0),
+        scan_tolerance: params.float("scan_tolerance") as f32,
+        max_shoot_count: params.integer("max_shoot_count") as i32,
+        track_confidence: params.float("track_confidence"),
+        shoot_deltas: shoot_deltas,
+        verbose: true
+    })
+}
+
+/// Every selectable AI. Add yours here to make it available on the command line.
+pub static REGISTRY: &'static [AiEntry] = &[
+    AiEntry {
+        name: "random",
+        description: "Wanders about at random while mapping asteroids, then scans and shoots at whatever it finds",
+        params: &[
+            ParamSpec {
+                name: "scan_tolerance",
+                kind: ParamKind::Float,
+                default: "50",
+                description: "Percentage of the field mapped before radars are used",
+                min: Some(0.0),
+                max: Some(100.0)
+            },
+            ParamSpec {
+                name: "max_shoot_count",
+                kind: ParamKind::Integer,
+                default: "2",
+                description: "How far shots spread around a target that keeps being seen",
+                min: Some(0.0),
+                max: Some(10.0)
+            },
+            ParamSpec {
+                name: "shoot_pattern",
+                kind: ParamKind::Choice(&["ring", "target"]),
+                default: "ring",
+                description: "Spread shots on the hexes around the target, or always shoot at the target itself",
+                min: None,
+                max: None
+            },
+            ParamSpec {
+                name: "track_confidence",
+                kind: ParamKind::Float,
+                default: "0.5",
+                description: "Expected number of enemies in the blast before shooting where an enemy was tracked to",
+                min: Some(0.0),
+                max: None
+            }
+        ],
+        build: build_random
+    }
+];
+
+/// Returned for an `--ai` name that isn't in the registry.
+#[derive(Debug)]
+pub struct UnknownAi {
+    pub name: String,
+    pub close_matches: Vec<&'static str>
+}
+
+impl fmt::Display for UnknownAi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "there is no AI called {}", self.name));
+        if !self.close_matches.is_empty() {
+            try!(write!(f, ", did you mean {}?", self.close_matches.connect(" or ")));
+        }
+        let names: Vec<&str> = REGISTRY.iter().map(|entry| entry.name).collect();
+        write!(f, " Available AIs: {}", names.connect(", "))
+    }
+}
+
+/// Levenshtein distance, for suggesting names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current.push(min(substitution, min(previous[j + 1], current[j]) + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub fn find(name: &str) -> Result<&'static AiEntry, UnknownAi> {
+    match REGISTRY.iter().find(|entry| entry.name == name) {
+        Some(entry) => Ok(entry),
+        None => Err(UnknownAi {
+            name: name.to_string(),
+            close_matches: REGISTRY.iter()
+                .filter(|entry| edit_distance(&entry.name.to_lowercase(), &name.to_lowercase()) <= 2
+                        || entry.name.starts_with(name) || name.starts_with(entry.name))
+                .map(|entry| entry.name)
+                .collect()
+        })
+    }
+}
+
+/// The named AI with its default parameters.
+pub fn from_name(name: &str, rng: AiRng) -> Result<Box<Ai + Send>, UnknownAi> {
+    find(name).map(|entry| (entry.build)(rng, &entry.default_params()))
+}
+
+/// The `--list-ais` output.
+pub fn print_registry() {
+    for entry in REGISTRY.iter() {
+        println!("{}: {}", entry.name, entry.description);
+        for param in entry.params.iter() {
+            println!("    {} ({:?}, default {}): {}", param.name, param.kind, param.default, param.description);
+        }
     }
 }
 
 #[test]
 fn test_from_name() {
-    from_name("random".to_string());
+    assert!(from_name("random", seeded_rng(1)).is_ok());
+    for entry in REGISTRY.iter() {
+        assert!(REGISTRY.iter().filter(|other| other.name == entry.name).count() == 1);
+    }
 }
 
 #[test]
-#[should_panic]
 fn test_from_name_nonsense() {
-    from_name("not an actual ai".to_string());
+    match from_name("not an actual ai", seeded_rng(1)) {
+        Err(e) => assert!(e.close_matches.is_empty()),
+        Ok(_) => panic!("Found an AI that doesn't exist")
+    }
+    match from_name("randon", seeded_rng(1)) {
+        Err(e) => {
+            assert!(e.close_matches == vec!["random"]);
+            assert!(e.to_string().contains("did you mean random?"));
+        },
+        Ok(_) => panic!("Found an AI that doesn't exist")
+    }
+}
+
+#[test]
+fn test_params() {
+    let random = find("random").unwrap();
+    let defaults = random.default_params();
+    assert!(defaults.float("scan_tolerance") == 50.0);
+    assert!(defaults.integer("max_shoot_count") == 2);
+    assert!(defaults.choice("shoot_pattern") == "ring");
+
+    let opts = vec![split_opt("scan_tolerance = 75.5").unwrap(), split_opt("shoot_pattern=target").unwrap()];
+    let params = random.params(&opts).unwrap();
+    assert!(params.float("scan_tolerance") == 75.5);
+    assert!(params.choice("shoot_pattern") == "target");
+
+    assert!(split_opt("scan_tolerance").is_err());
+    let bad = |key: &str, value: &str| random.params(&[(key.to_string(), value.to_string())]).is_err();
+    assert!(bad("max_shoot_count", "two"));
+    assert!(bad("shoot_pattern", "spiral"));
+    assert!(bad("aggression", "11"));
+    assert!(bad("scan_tolerance", "-5"));
+    assert!(bad("scan_tolerance", "100.5"));
+    assert!(bad("max_shoot_count", "-1"));
+    assert!(bad("track_confidence", "-0.1"));
+    assert!(!bad("scan_tolerance", "100"));
+}
+
+#[test]
+fn test_same_seed_same_actions() {
+    use super::incoming::{parse_message, Message};
+    let play = |seed: u64| {
+        let mut ai = from_name("random", seeded_rng(seed)).unwrap();
+        let mut actions = Vec::new();
+        for _ in 0..5 {
+            match parse_message(include_str!("../incoming/fixtures/events.json")).unwrap() {
+                Message::EventsMessage(msg) => {
+                    ai.set_state(msg.config, msg.you, msg.other_teams);
+                    actions.push(ai.respond(msg.events));
+                },
+                _ => panic!("Fixture isn't an events message")
+            }
+        }
+        actions
+    };
+    assert!(play(42) == play(42));
+}
+
+#[bench]
+fn bench_respond_large_field(b: &mut Bencher) {
+    use super::rules::{self, Game};
+    let config = GameConfig { field_radius: 40, asteroids: Some(300), ..rules::default_config() };
+    let game = Game::new(config, &["Ferrous Bueller".to_string(), "Rooney".to_string()], &mut seeded_rng(1));
+    let mut ai = from_name("random", seeded_rng(1)).unwrap();
+    ai.on_game_start(game.start_message(1));
+    b.iter(|| {
+        let mut events = game.first_events();
+        let msg = game.events_message(1, events.remove(&1).unwrap());
+        ai.set_state(msg.config, msg.you, msg.other_teams);
+        ai.respond(msg.events)
+    });
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct MoveAction {
     pub bot_id: u32,
     pub pos: Position
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct RadarAction {
     pub bot_id: u32,
     pub pos: Position
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct CannonAction {
     pub bot_id: u32,
     pub pos: Position
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Action {
     CannonAction(CannonAction),
     MoveAction(MoveAction),
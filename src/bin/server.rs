@@ -0,0 +1,59 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+extern crate tyckiting;
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+
+use tyckiting::{ai, rules, server};
+
+static USAGE: &'static str = "
+tyckiting-server - a stand-in for the Tyckiting server
+
+Usage: tyckiting-server [--port <port>] [--teams <n>] [--seed <seed>]
+       tyckiting-server [-h]
+
+Options:
+  -h, --help                Show this help message
+  -P, --port <port>         Port to listen on [default: 3000]
+  --teams <n>               Number of teams to wait for [default: 2]
+  --seed <seed>             Seed for the asteroids and starting positions, random if not given
+
+Plays one game on this machine between the clients that connect to it, using
+the default rules of the Tyckiting server, and exits.
+";
+
+#[derive(RustcDecodable, Debug)]
+struct Args {
+    flag_port: u32,
+    flag_teams: usize,
+    flag_seed: Option<u64>
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
+    let seed = args.flag_seed.unwrap_or_else(ai::random_seed);
+    println!("Using seed {}", seed);
+    if let Err(e) = server::run(args.flag_port, args.flag_teams, rules::default_config(), seed) {
+        println!("Couldn't run the server: {}", e);
+        std::process::exit(1);
+    }
+}
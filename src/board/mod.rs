@@ -0,0 +1,148 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::cmp::{max, min};
+
+use super::{Position, GameConfig};
+
+/// The playing field: every hex within `field_radius` of the centre, edge
+/// included. The hexes are numbered from 0 to `len() - 1` column by column,
+/// so AIs can keep per-hex data in plain arrays.
+#[derive(Debug, Clone)]
+pub struct Board {
+    radius: i32,
+    hexes: Vec<Position>
+}
+
+impl Board {
+    pub fn new(config: &GameConfig) -> Board {
+        Board::with_radius(config.field_radius)
+    }
+
+    pub fn with_radius(radius: i32) -> Board {
+        let radius = max(radius, 0);
+        let mut hexes = Vec::with_capacity(hex_count(radius));
+        for x in -radius..radius + 1 {
+            for y in column_start(x, radius)..column_end(x, radius) + 1 {
+                hexes.push(Position { x: x, y: y });
+            }
+        }
+        Board { radius: radius, hexes: hexes }
+    }
+
+    pub fn radius(&self) -> i32 {
+        self.radius
+    }
+
+    pub fn len(&self) -> usize {
+        self.hexes.len()
+    }
+
+    /// Every legal hex, in index order.
+    pub fn hexes(&self) -> &[Position] {
+        &self.hexes
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        pos.distance(Position { x: 0, y: 0 }) <= self.radius
+    }
+
+    /// The hexes of `hexes` that are on the field.
+    pub fn clip(&self, hexes: Vec<Position>) -> Vec<Position> {
+        hexes.into_iter().filter(|pos| self.contains(*pos)).collect()
+    }
+
+    /// `pos` itself if it is on the field, otherwise the legal hex closest to it.
+    pub fn nearest(&self, pos: Position) -> Position {
+        if self.contains(pos) {
+            pos
+        }
+        else {
+            Position { x: 0, y: 0 }.line_to(pos)[self.radius as usize]
+        }
+    }
+
+    pub fn index(&self, pos: Position) -> Option<usize> {
+        hex_index(self.radius, pos)
+    }
+
+    pub fn position(&self, index: usize) -> Position {
+        self.hexes[index]
+    }
+}
+
+/// The number of hexes within `radius` of the centre.
+pub fn hex_count(radius: i32) -> usize {
+    (3 * radius * (radius + 1) + 1) as usize
+}
+
+/// The index `Board::with_radius(radius)` gives `pos`, without building the
+/// board.
+pub fn hex_index(radius: i32, pos: Position) -> Option<usize> {
+    if pos.distance(Position { x: 0, y: 0 }) > radius {
+        return None;
+    }
+    // columns left of the centre grow by one hex each, the ones right of it shrink
+    let column = pos.x + radius;
+    let left = min(column, radius);
+    let right = column - left;
+    let offset = left * (radius + 1) + left * (left - 1) / 2
+        + right * (2 * radius + 1) - right * (right - 1) / 2;
+    Some((offset + pos.y - column_start(pos.x, radius)) as usize)
+}
+
+pub fn column_start(x: i32, radius: i32) -> i32 {
+    max(-radius, -x - radius)
+}
+
+pub fn column_end(x: i32, radius: i32) -> i32 {
+    if x > 0 { radius - x } else { radius }
+}
+
+#[test]
+fn test_board_indexes() {
+    for radius in 0..15 {
+        let board = Board::with_radius(radius);
+        assert!(board.len() as i32 == 3 * radius * (radius + 1) + 1);
+        assert!(board.len() == hex_count(radius));
+        for (i, &pos) in board.hexes().iter().enumerate() {
+            assert!(board.index(pos) == Some(i));
+            assert!(board.position(i) == pos);
+        }
+        for pos in Position { x: 0, y: 0 }.spiral(radius as u32 + 2).into_iter() {
+            let inside = pos.distance(Position { x: 0, y: 0 }) <= radius;
+            assert!(board.contains(pos) == inside);
+            assert!(board.index(pos).is_some() == inside);
+        }
+    }
+}
+
+#[test]
+fn test_board_clip_and_nearest() {
+    let config = GameConfig { field_radius: 5, ..Default::default() };
+    let board = Board::new(&config);
+    // the edge is part of the field
+    assert!(board.clip(Position { x: 4, y: 0 }.ring(1)).len() == 6);
+    assert!(board.clip(Position { x: 5, y: 0 }.ring(1)).len() == 3);
+    let center = Position { x: 0, y: 0 };
+    for pos in center.spiral(12).into_iter() {
+        let nearest = board.nearest(pos);
+        assert!(board.contains(nearest));
+        assert!(nearest.distance(pos) == max(0, pos.distance(center) - 5));
+    }
+}
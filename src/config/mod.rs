@@ -0,0 +1,426 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate toml;
+
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Read when `--config` isn't given, if it exists.
+pub const DEFAULT_PATH: &'static str = "tyckiting.toml";
+
+/// Everything that can go wrong while reading a config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Syntax(String),
+    WrongType(String),
+    BadEnv(&'static str, String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "couldn't read the config file: {}", e),
+            ConfigError::Syntax(ref e) => write!(f, "the config file isn't valid TOML: {}", e),
+            ConfigError::WrongType(ref key) => write!(f, "{} in the config file has the wrong type", key),
+            ConfigError::BadEnv(var, ref value) => write!(f, "{} has an invalid value {}", var, value)
+        }
+    }
+}
+
+/// A parsed TOML config file. Client options are top level keys and AI
+/// parameters go in a table per AI:
+///
+/// ```toml
+/// host = "localhost"
+/// port = 3000
+/// name = "Ferrous Bueller"
+/// ai = "random"
+/// record = "games"
+/// webgame = "Rooney"
+/// user = "ferrous"
+/// password = "secret"
+/// api_url = "http://localhost/api/new"
+/// each = false
+/// repeat = 1
+/// validate = "repair"
+/// retries = 5
+/// retry_delay = 500
+/// keep_ai_state = false
+/// margin = 50
+/// seed = 42
+///
+/// [params.random]
+/// scan_tolerance = 60
+/// shoot_pattern = "target"
+/// ```
+pub struct ConfigFile {
+    table: toml::Table
+}
+
+impl ConfigFile {
+    pub fn parse(contents: &str) -> Result<ConfigFile, ConfigError> {
+        let mut parser = toml::Parser::new(contents);
+        match parser.parse() {
+            Some(table) => Ok(ConfigFile { table: table }),
+            None => {
+                let errors: Vec<String> = parser.errors.iter().map(|e| {
+                    let (line, col) = parser.to_linecol(e.lo);
+                    format!("{} at line {}, column {}", e.desc, line + 1, col + 1)
+                }).collect();
+                Err(ConfigError::Syntax(errors.connect("; ")))
+            }
+        }
+    }
+
+    pub fn load(path: &str) -> Result<ConfigFile, ConfigError> {
+        let mut contents = String::new();
+        try!(File::open(path).and_then(|mut file| file.read_to_string(&mut contents)).map_err(ConfigError::Io));
+        ConfigFile::parse(&contents)
+    }
+
+    /// Reads `path`, or `DEFAULT_PATH` if no path is given and it exists.
+    pub fn find(path: Option<&str>) -> Result<Option<ConfigFile>, ConfigError> {
+        match path {
+            Some(path) => ConfigFile::load(path).map(Some),
+            None if Path::new(DEFAULT_PATH).exists() => ConfigFile::load(DEFAULT_PATH).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.table.get(key) {
+            Some(&toml::Value::String(ref value)) => Ok(Some(value.clone())),
+            Some(_) => Err(ConfigError::WrongType(key.to_string())),
+            None => Ok(None)
+        }
+    }
+
+    fn integer(&self, key: &str) -> Result<Option<i64>, ConfigError> {
+        match self.table.get(key) {
+            Some(&toml::Value::Integer(value)) => Ok(Some(value)),
+            Some(_) => Err(ConfigError::WrongType(key.to_string())),
+            None => Ok(None)
+        }
+    }
+
+    /// An integer that has to fit in `T`, e.g. no negative counts.
+    fn unsigned<T: FromStr>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        match try!(self.integer(key)) {
+            Some(value) => value.to_string().parse().map(Some).map_err(|_| ConfigError::WrongType(key.to_string())),
+            None => Ok(None)
+        }
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.table.get(key) {
+            Some(&toml::Value::Boolean(value)) => Ok(Some(value)),
+            Some(_) => Err(ConfigError::WrongType(key.to_string())),
+            None => Ok(None)
+        }
+    }
+
+    /// The `[params.<ai_name>]` table as key and value pairs, in the same
+    /// form as `--ai-opt`.
+    pub fn ai_params(&self, ai_name: &str) -> Result<Vec<(String, String)>, ConfigError> {
+        let params = match self.table.get("params").and_then(|params| params.lookup(ai_name)) {
+            Some(&toml::Value::Table(ref params)) => params,
+            Some(_) => return Err(ConfigError::WrongType(format!("params.{}", ai_name))),
+            None => return Ok(Vec::new())
+        };
+        let mut opts = Vec::new();
+        for (key, value) in params.iter() {
+            let value = match *value {
+                toml::Value::String(ref s) => s.clone(),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => return Err(ConfigError::WrongType(format!("params.{}.{}", ai_name, key)))
+            };
+            opts.push((key.clone(), value));
+        }
+        Ok(opts)
+    }
+}
+
+/// Client options given on the command line, `None` where a flag is absent.
+#[derive(Default)]
+pub struct Overrides {
+    pub host: Option<String>,
+    pub port: Option<u32>,
+    pub team_name: Option<String>,
+    pub ai: Option<String>,
+    pub ai_opts: Vec<(String, String)>,
+    pub record: Option<String>,
+    pub webgame: Option<String>,
+    pub api_url: Option<String>,
+    pub each: Option<bool>,
+    pub repeat: Option<u32>,
+    pub validate: Option<String>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<u32>,
+    pub keep_ai_state: Option<bool>,
+    pub margin: Option<u32>,
+    pub seed: Option<u64>
+}
+
+/// The client options after combining, from lowest to highest priority, the
+/// defaults, the config file, the command line and the environment.
+#[derive(Debug)]
+pub struct Settings {
+    pub host: String,
+    pub port: u32,
+    pub team_name: String,
+    pub ai: String,
+    pub ai_opts: Vec<(String, String)>,
+    pub record: Option<String>,
+    pub webgame: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub api_url: Option<String>,
+    pub each: bool,
+    pub repeat: u32,
+    pub validate: String,
+    pub retries: u32,
+    pub retry_delay: u32,
+    pub keep_ai_state: bool,
+    pub margin: u32,
+    /// Random if not given anywhere
+    pub seed: Option<u64>
+}
+
+fn env_opts(var: &'static str, value: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    value.split(',').filter(|opt| !opt.trim().is_empty()).map(|opt| {
+        let parts: Vec<&str> = opt.splitn(2, '=').collect();
+        if parts.len() == 2 {
+            Ok((parts[0].trim().to_string(), parts[1].trim().to_string()))
+        }
+        else {
+            Err(ConfigError::BadEnv(var, value.to_string()))
+        }
+    }).collect()
+}
+
+fn env_value<T: FromStr, E>(env: &E, var: &'static str) -> Result<Option<T>, ConfigError>
+    where E: Fn(&str) -> Option<String> {
+    match env(var) {
+        Some(value) => value.trim().parse().map(Some).map_err(|_| ConfigError::BadEnv(var, value.clone())),
+        None => Ok(None)
+    }
+}
+
+impl Settings {
+    /// `env` looks up an environment variable; the variables read are
+    /// `TYCKITING_HOST`, `TYCKITING_PORT`, `TYCKITING_NAME`, `TYCKITING_AI`,
+    /// `TYCKITING_AI_OPTS` (comma separated key=value pairs), `TYCKITING_RECORD`,
+    /// `TYCKITING_WEBGAME`, `TYCKITING_USER`, `TYCKITING_PASSWORD`,
+    /// `TYCKITING_API_URL`, `TYCKITING_EACH`, `TYCKITING_REPEAT`,
+    /// `TYCKITING_VALIDATE`, `TYCKITING_RETRIES`, `TYCKITING_RETRY_DELAY`,
+    /// `TYCKITING_KEEP_AI_STATE`, `TYCKITING_MARGIN` and `TYCKITING_SEED`.
+    /// The web API credentials can't be given on the command line, where
+    /// other users could see them.
+    pub fn resolve<E>(file: Option<&ConfigFile>, cli: Overrides, env: E) -> Result<Settings, ConfigError>
+        where E: Fn(&str) -> Option<String> {
+        let file_string = |key: &str| -> Result<Option<String>, ConfigError> {
+            match file {
+                Some(file) => file.string(key),
+                None => Ok(None)
+            }
+        };
+        let file_port = match file {
+            Some(file) => try!(file.integer("port")).map(|port| port as u32),
+            None => None
+        };
+        let env_port = match env("TYCKITING_PORT") {
+            Some(value) => Some(try!(value.parse().map_err(|_| ConfigError::BadEnv("TYCKITING_PORT", value.clone())))),
+            None => None
+        };
+        let file_unsigned = |key: &str| -> Result<Option<u32>, ConfigError> {
+            match file {
+                Some(file) => file.unsigned(key),
+                None => Ok(None)
+            }
+        };
+        let file_boolean = |key: &str| -> Result<Option<bool>, ConfigError> {
+            match file {
+                Some(file) => file.boolean(key),
+                None => Ok(None)
+            }
+        };
+        let file_seed: Option<u64> = match file {
+            Some(file) => try!(file.unsigned("seed")),
+            None => None
+        };
+
+        let ai = env("TYCKITING_AI").or(cli.ai).or(try!(file_string("ai"))).unwrap_or_else(|| "random".to_string());
+        let mut ai_opts = match file {
+            Some(file) => try!(file.ai_params(&ai)),
+            None => Vec::new()
+        };
+        ai_opts.extend(cli.ai_opts.into_iter());
+        if let Some(value) = env("TYCKITING_AI_OPTS") {
+            ai_opts.extend(try!(env_opts("TYCKITING_AI_OPTS", &value)).into_iter());
+        }
+
+        Ok(Settings {
+            host: env("TYCKITING_HOST").or(cli.host).or(try!(file_string("host"))).unwrap_or_else(|| "localhost".to_string()),
+            port: env_port.or(cli.port).or(file_port).unwrap_or(3000),
+            team_name: env("TYCKITING_NAME").or(cli.team_name).or(try!(file_string("name")))
+                .unwrap_or_else(|| "Ferrous Bueller".to_string()),
+            ai: ai,
+            ai_opts: ai_opts,
+            record: env("TYCKITING_RECORD").or(cli.record).or(try!(file_string("record"))),
+            webgame: env("TYCKITING_WEBGAME").or(cli.webgame).or(try!(file_string("webgame"))),
+            user: env("TYCKITING_USER").or(try!(file_string("user"))),
+            password: env("TYCKITING_PASSWORD").or(try!(file_string("password"))),
+            api_url: env("TYCKITING_API_URL").or(cli.api_url).or(try!(file_string("api_url"))),
+            each: try!(env_value(&env, "TYCKITING_EACH")).or(cli.each).or(try!(file_boolean("each"))).unwrap_or(false),
+            repeat: try!(env_value(&env, "TYCKITING_REPEAT")).or(cli.repeat).or(try!(file_unsigned("repeat"))).unwrap_or(1),
+            validate: env("TYCKITING_VALIDATE").or(cli.validate).or(try!(file_string("validate")))
+                .unwrap_or_else(|| "report".to_string()),
+            retries: try!(env_value(&env, "TYCKITING_RETRIES")).or(cli.retries).or(try!(file_unsigned("retries"))).unwrap_or(5),
+            retry_delay: try!(env_value(&env, "TYCKITING_RETRY_DELAY")).or(cli.retry_delay)
+                .or(try!(file_unsigned("retry_delay"))).unwrap_or(500),
+            keep_ai_state: try!(env_value(&env, "TYCKITING_KEEP_AI_STATE")).or(cli.keep_ai_state)
+                .or(try!(file_boolean("keep_ai_state"))).unwrap_or(false),
+            margin: try!(env_value(&env, "TYCKITING_MARGIN")).or(cli.margin).or(try!(file_unsigned("margin"))).unwrap_or(50),
+            seed: try!(env_value(&env, "TYCKITING_SEED")).or(cli.seed).or(file_seed)
+        })
+    }
+
+    /// `resolve` with the process environment.
+    pub fn from_env(file: Option<&ConfigFile>, cli: Overrides) -> Result<Settings, ConfigError> {
+        Settings::resolve(file, cli, |var| env::var(var).ok())
+    }
+}
+
+#[test]
+fn test_ai_params() {
+    let config = ConfigFile::parse("[params.random]\nscan_tolerance = 60.5\nmax_shoot_count = 3\nshoot_pattern = \"target\"\n").unwrap();
+    let opts = config.ai_params("random").unwrap();
+    assert!(opts == vec![("max_shoot_count".to_string(), "3".to_string()),
+                         ("scan_tolerance".to_string(), "60.5".to_string()),
+                         ("shoot_pattern".to_string(), "target".to_string())]);
+    assert!(config.ai_params("other").unwrap().is_empty());
+    assert!(ConfigFile::parse("[params.random]\nshoot_pattern = [1, 2]\n").unwrap().ai_params("random").is_err());
+    assert!(ConfigFile::parse("[params.random\n").is_err());
+}
+
+#[test]
+fn test_settings_priority() {
+    use std::collections::BTreeMap;
+    let file = ConfigFile::parse("host = \"file.example\"\nport = 4000\nname = \"From file\"\nai = \"random\"\n\
+                                  [params.random]\nscan_tolerance = 60\n").unwrap();
+    let cli = Overrides {
+        port: Some(5000),
+        team_name: Some("From CLI".to_string()),
+        ai_opts: vec![("scan_tolerance".to_string(), "70".to_string())],
+        ..Default::default()
+    };
+    let mut vars = BTreeMap::new();
+    vars.insert("TYCKITING_NAME", "From env".to_string());
+    vars.insert("TYCKITING_PASSWORD", "secret".to_string());
+    vars.insert("TYCKITING_AI_OPTS", "scan_tolerance=80, max_shoot_count=1".to_string());
+    let settings = Settings::resolve(Some(&file), cli, |var| vars.get(var).cloned()).unwrap();
+    assert!(settings.host == "file.example");
+    assert!(settings.port == 5000);
+    assert!(settings.team_name == "From env");
+    assert!(settings.ai == "random");
+    assert!(settings.ai_opts.iter().map(|&(ref k, ref v)| format!("{}={}", k, v)).collect::<Vec<_>>()
+            == vec!["scan_tolerance=60", "scan_tolerance=70", "scan_tolerance=80", "max_shoot_count=1"]);
+    assert!(settings.record.is_none());
+    assert!(settings.password == Some("secret".to_string()) && settings.user.is_none());
+
+    let defaults = Settings::resolve(None, Default::default(), |_| None).unwrap();
+    assert!(defaults.host == "localhost" && defaults.port == 3000 && defaults.ai == "random");
+
+    let bad_port = Settings::resolve(None, Default::default(), |var| {
+        if var == "TYCKITING_PORT" { Some("lots".to_string()) } else { None }
+    });
+    assert!(bad_port.is_err());
+}
+
+#[test]
+fn test_settings_client_options() {
+    use std::collections::BTreeMap;
+    let file = ConfigFile::parse("each = true\nrepeat = 3\nvalidate = \"repair\"\nretries = 7\nretry_delay = 100\n\
+                                  keep_ai_state = true\nmargin = 20\nseed = 42\n").unwrap();
+
+    // the file alone
+    let settings = Settings::resolve(Some(&file), Default::default(), |_| None).unwrap();
+    assert!(settings.each && settings.repeat == 3);
+    assert!(settings.validate == "repair");
+    assert!(settings.retries == 7 && settings.retry_delay == 100);
+    assert!(settings.keep_ai_state && settings.margin == 20);
+    assert!(settings.seed == Some(42));
+
+    // the command line over the file
+    let cli = || Overrides {
+        each: Some(false),
+        repeat: Some(4),
+        validate: Some("report".to_string()),
+        retries: Some(8),
+        retry_delay: Some(200),
+        keep_ai_state: Some(false),
+        margin: Some(30),
+        seed: Some(43),
+        ..Default::default()
+    };
+    let settings = Settings::resolve(Some(&file), cli(), |_| None).unwrap();
+    assert!(!settings.each && settings.repeat == 4);
+    assert!(settings.validate == "report");
+    assert!(settings.retries == 8 && settings.retry_delay == 200);
+    assert!(!settings.keep_ai_state && settings.margin == 30);
+    assert!(settings.seed == Some(43));
+
+    // the environment over both
+    let mut vars = BTreeMap::new();
+    vars.insert("TYCKITING_EACH", "true".to_string());
+    vars.insert("TYCKITING_REPEAT", "5".to_string());
+    vars.insert("TYCKITING_VALIDATE", "repair".to_string());
+    vars.insert("TYCKITING_RETRIES", "9".to_string());
+    vars.insert("TYCKITING_RETRY_DELAY", "300".to_string());
+    vars.insert("TYCKITING_KEEP_AI_STATE", "true".to_string());
+    vars.insert("TYCKITING_MARGIN", "40".to_string());
+    vars.insert("TYCKITING_SEED", "44".to_string());
+    let settings = Settings::resolve(Some(&file), cli(), |var| vars.get(var).cloned()).unwrap();
+    assert!(settings.each && settings.repeat == 5);
+    assert!(settings.validate == "repair");
+    assert!(settings.retries == 9 && settings.retry_delay == 300);
+    assert!(settings.keep_ai_state && settings.margin == 40);
+    assert!(settings.seed == Some(44));
+
+    let defaults = Settings::resolve(None, Default::default(), |_| None).unwrap();
+    assert!(!defaults.each && defaults.repeat == 1 && defaults.validate == "report");
+    assert!(defaults.retries == 5 && defaults.retry_delay == 500);
+    assert!(!defaults.keep_ai_state && defaults.margin == 50 && defaults.seed.is_none());
+
+    let bad_env = Settings::resolve(None, Default::default(), |var| {
+        if var == "TYCKITING_KEEP_AI_STATE" { Some("sometimes".to_string()) } else { None }
+    });
+    assert!(bad_env.is_err());
+    let negative = ConfigFile::parse("retries = -1\n").unwrap();
+    assert!(Settings::resolve(Some(&negative), Default::default(), |_| None).is_err());
+    let wrong_type = ConfigFile::parse("each = \"yes\"\n").unwrap();
+    assert!(Settings::resolve(Some(&wrong_type), Default::default(), |_| None).is_err());
+}
@@ -0,0 +1,341 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+// Geometry on the hex grid. `Position` uses axial coordinates, the third
+// cube coordinate is `-x - y`.
+// See http://www.redblobgames.com/grids/hexagons/
+
+use std::cmp::{max, min};
+use std::ops::{Add, Sub, Neg, Mul};
+
+use super::Position;
+
+/// The six neighbours of a hex, in counter clockwise order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    East,
+    NorthEast,
+    NorthWest,
+    West,
+    SouthWest,
+    SouthEast
+}
+
+pub const DIRECTIONS: [Direction; 6] = [
+    Direction::East,
+    Direction::NorthEast,
+    Direction::NorthWest,
+    Direction::West,
+    Direction::SouthWest,
+    Direction::SouthEast
+];
+
+impl Direction {
+    pub fn delta(self) -> Position {
+        match self {
+            Direction::East => Position { x: 1, y: 0 },
+            Direction::NorthEast => Position { x: 1, y: -1 },
+            Direction::NorthWest => Position { x: 0, y: -1 },
+            Direction::West => Position { x: -1, y: 0 },
+            Direction::SouthWest => Position { x: -1, y: 1 },
+            Direction::SouthEast => Position { x: 0, y: 1 }
+        }
+    }
+
+    /// Turns counter clockwise by `steps` sixths of a full turn, clockwise if negative.
+    #[allow(dead_code)]
+    pub fn rotate(self, steps: i32) -> Direction {
+        let index = DIRECTIONS.iter().position(|&d| d == self).unwrap() as i32;
+        DIRECTIONS[sixths(index + steps)]
+    }
+
+    #[allow(dead_code)]
+    pub fn opposite(self) -> Direction {
+        self.rotate(3)
+    }
+}
+
+/// `steps` sixths of a turn as a number of counter clockwise steps, 0 to 5.
+fn sixths(steps: i32) -> usize {
+    (((steps % 6) + 6) % 6) as usize
+}
+
+/// The three axes of the grid, named after the cube coordinate that stays
+/// the same when reflecting across them.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z
+}
+
+/// A hex in cube coordinates, `x + y + z == 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cube {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32
+}
+
+impl Cube {
+    pub fn from_position(pos: Position) -> Cube {
+        Cube { x: pos.x, y: pos.y, z: -pos.x - pos.y }
+    }
+
+    pub fn to_position(self) -> Position {
+        Position { x: self.x, y: self.y }
+    }
+
+    /// The hex containing the fractional cube coordinate.
+    pub fn round(x: f64, y: f64, z: f64) -> Cube {
+        let (mut rx, mut ry, mut rz) = (x.round(), y.round(), z.round());
+        let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+        if dx > dy && dx > dz {
+            rx = -ry - rz;
+        }
+        else if dy > dz {
+            ry = -rx - rz;
+        }
+        else {
+            rz = -rx - ry;
+        }
+        Cube { x: rx as i32, y: ry as i32, z: rz as i32 }
+    }
+}
+
+impl Add for Position {
+    type Output = Position;
+    fn add(self, other: Position) -> Position {
+        Position { x: self.x + other.x, y: self.y + other.y }
+    }
+}
+
+impl Sub for Position {
+    type Output = Position;
+    fn sub(self, other: Position) -> Position {
+        Position { x: self.x - other.x, y: self.y - other.y }
+    }
+}
+
+impl Neg for Position {
+    type Output = Position;
+    fn neg(self) -> Position {
+        Position { x: -self.x, y: -self.y }
+    }
+}
+
+impl Mul<i32> for Position {
+    type Output = Position;
+    fn mul(self, factor: i32) -> Position {
+        Position { x: self.x * factor, y: self.y * factor }
+    }
+}
+
+impl Position {
+    pub fn distance(&self, other: Position) -> i32 {
+        max((other.x - self.x).abs(),
+            max((other.y - self.y).abs(),
+            ((-other.x - other.y) - (-self.x - self.y)).abs()))
+    }
+
+    pub fn neighbor(&self, direction: Direction) -> Position {
+        *self + direction.delta()
+    }
+
+    pub fn neighbors(&self) -> Vec<Position> {
+        DIRECTIONS.iter().map(|&d| self.neighbor(d)).collect()
+    }
+
+    /// The hexes at exactly `radius` steps, starting from the south west
+    /// corner and walking counter clockwise.
+    #[allow(dead_code)]
+    pub fn ring(&self, radius: u32) -> Vec<Position> {
+        if radius == 0 {
+            return vec![*self];
+        }
+        let mut ret = Vec::with_capacity(6 * radius as usize);
+        let mut pos = *self + Direction::SouthWest.delta() * radius as i32;
+        for &direction in DIRECTIONS.iter() {
+            for _ in 0..radius {
+                ret.push(pos);
+                pos = pos.neighbor(direction);
+            }
+        }
+        ret
+    }
+
+    /// This hex followed by the rings around it out to `radius`.
+    #[allow(dead_code)]
+    pub fn spiral(&self, radius: u32) -> Vec<Position> {
+        (0..radius + 1).flat_map(|r| self.ring(r).into_iter()).collect()
+    }
+
+    /// The hexes on a straight line from here to `other`, both included.
+    pub fn line_to(&self, other: Position) -> Vec<Position> {
+        let steps = self.distance(other);
+        if steps == 0 {
+            return vec![*self];
+        }
+        // nudged off the edges between hexes so that rounding is consistent
+        let (a, b) = (Cube::from_position(*self), Cube::from_position(other));
+        let (ax, ay, az) = (a.x as f64 + 1e-6, a.y as f64 + 2e-6, a.z as f64 - 3e-6);
+        let (bx, by, bz) = (b.x as f64 + 1e-6, b.y as f64 + 2e-6, b.z as f64 - 3e-6);
+        (0..steps + 1).map(|i| {
+            let t = i as f64 / steps as f64;
+            Cube::round(ax + (bx - ax) * t, ay + (by - ay) * t, az + (bz - az) * t).to_position()
+        }).collect()
+    }
+
+    /// Turns this hex around `center` counter clockwise by `steps` sixths of
+    /// a full turn, clockwise if negative.
+    #[allow(dead_code)]
+    pub fn rotate_around(&self, center: Position, steps: i32) -> Position {
+        let mut cube = Cube::from_position(*self - center);
+        for _ in 0..sixths(steps) {
+            cube = Cube { x: -cube.z, y: -cube.x, z: -cube.y };
+        }
+        cube.to_position() + center
+    }
+
+    /// Mirrors this hex across the line through `center` along `axis`.
+    #[allow(dead_code)]
+    pub fn reflect_across(&self, center: Position, axis: Axis) -> Position {
+        let c = Cube::from_position(*self - center);
+        let reflected = match axis {
+            Axis::X => Cube { x: c.x, y: c.z, z: c.y },
+            Axis::Y => Cube { x: c.z, y: c.y, z: c.x },
+            Axis::Z => Cube { x: c.y, y: c.x, z: c.z }
+        };
+        reflected.to_position() + center
+    }
+
+    pub fn positions_within(&self, distance: u32) -> Vec<Position> {
+        let dist = distance as i32;
+        let mut ret: Vec<Position> = Vec::new();
+        for dx in -dist..dist + 1 {
+            for dy in (max(-dist, -dx - dist)..min(dist, -dx + dist) + 1) {
+                ret.push(Position { x: self.x + dx, y: self.y + dy});
+            }
+        }
+        ret
+    }
+
+    pub fn contains_any_within(&self, positions: Vec<Position>, steps: i32) -> bool {
+        positions.iter().any(|pos| pos.distance(*self) <= steps)
+    }
+}
+
+#[test]
+fn check_distance() {
+    assert!(Position { x: 0, y: 0}.distance(Position {x: 1, y: 0}) == 1);
+    assert!(Position { x: 0, y: 0}.distance(Position {x: 1, y: 3}) == 4);
+    assert!(Position { x: 0, y: 0}.distance(Position {x: 0, y: 5}) == 5);
+}
+
+#[test]
+fn check_positions_within() {
+    let pos = Position { x: 0, y: 0};
+    assert!( pos.positions_within(1).len() == 7);
+    assert!( pos.positions_within(2).len() == 19);
+}
+
+#[cfg(test)]
+fn origin() -> Position {
+    Position { x: 0, y: 0 }
+}
+
+#[test]
+fn check_rings_and_spirals() {
+    let center = Position { x: 2, y: -1 };
+    for radius in 0..12 {
+        let ring = center.ring(radius);
+        assert!(ring.len() == if radius == 0 { 1 } else { 6 * radius as usize });
+        assert!(ring.iter().all(|pos| pos.distance(center) == radius as i32));
+        assert!(ring.iter().enumerate().all(|(i, pos)| !ring[i + 1..].contains(pos)));
+
+        let mut spiral = center.spiral(radius);
+        let mut within = center.positions_within(radius);
+        spiral.sort_by(|a, b| (a.x, a.y).cmp(&(b.x, b.y)));
+        within.sort_by(|a, b| (a.x, a.y).cmp(&(b.x, b.y)));
+        assert!(spiral == within);
+    }
+    assert!(origin().neighbors().iter().all(|n| origin().ring(1).contains(n)));
+}
+
+// every pair and triple of hexes within two steps of the origin
+#[test]
+fn check_distance_symmetry_and_triangle_inequality() {
+    let hexes = origin().spiral(2);
+    for &a in hexes.iter() {
+        assert!(a.distance(a) == 0);
+        for &b in hexes.iter() {
+            assert!(a.distance(b) == b.distance(a));
+            assert!(a.distance(b) == (a - b).distance(origin()));
+            for &c in hexes.iter() {
+                assert!(a.distance(c) <= a.distance(b) + b.distance(c));
+            }
+        }
+    }
+}
+
+#[test]
+fn check_lines() {
+    let hexes = origin().spiral(4);
+    for &a in hexes.iter() {
+        for &b in hexes.iter() {
+            let line = a.line_to(b);
+            assert!(line.len() as i32 == a.distance(b) + 1);
+            assert!(line[0] == a && line[line.len() - 1] == b);
+            assert!(line.windows(2).all(|pair| pair[0].distance(pair[1]) == 1));
+        }
+    }
+}
+
+#[test]
+fn check_rotation_and_reflection() {
+    let center = Position { x: -1, y: 3 };
+    for &direction in DIRECTIONS.iter() {
+        assert!(direction.delta().rotate_around(origin(), 1) == direction.rotate(1).delta());
+        assert!(direction.rotate(-1).rotate(1) == direction);
+        assert!(direction.delta() + direction.opposite().delta() == origin());
+    }
+    for &pos in center.spiral(4).iter() {
+        assert!(pos.rotate_around(center, 6) == pos);
+        assert!(pos.rotate_around(center, 2).rotate_around(center, -2) == pos);
+        assert!(pos.rotate_around(center, 1).distance(center) == pos.distance(center));
+        for &axis in [Axis::X, Axis::Y, Axis::Z].iter() {
+            let reflected = pos.reflect_across(center, axis);
+            assert!(reflected.reflect_across(center, axis) == pos);
+            assert!(reflected.distance(center) == pos.distance(center));
+        }
+    }
+}
+
+#[test]
+fn check_operators() {
+    let a = Position { x: 3, y: -2 };
+    let b = Position { x: -1, y: 4 };
+    assert!(a + b == Position { x: 2, y: 2 });
+    assert!(a - b == Position { x: 4, y: -6 });
+    assert!(-a == Position { x: -3, y: 2 });
+    assert!(a * 2 == a + a);
+    assert!(Cube::from_position(a).to_position() == a);
+    let cube = Cube::from_position(b);
+    assert!(cube.x + cube.y + cube.z == 0);
+}
@@ -0,0 +1,321 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::mem;
+
+use super::Position;
+use super::board::{self, Board};
+
+#[cfg(test)] use test::Bencher;
+
+/// A set of hexes on a field of a given radius, one bit per hex. Hexes off the
+/// field are never in the set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HexSet {
+    radius: i32,
+    bits: Vec<u64>,
+    len: usize
+}
+
+impl HexSet {
+    pub fn new(radius: i32) -> HexSet {
+        HexSet { radius: radius, bits: vec![0; (board::hex_count(radius) + 63) / 64], len: 0 }
+    }
+
+    pub fn for_board(board: &Board) -> HexSet {
+        HexSet::new(board.radius())
+    }
+
+    #[allow(dead_code)]
+    pub fn from_positions<I: IntoIterator<Item=Position>>(radius: i32, positions: I) -> HexSet {
+        let mut set = HexSet::new(radius);
+        for pos in positions {
+            set.insert(pos);
+        }
+        set
+    }
+
+    pub fn radius(&self) -> i32 {
+        self.radius
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        match board::hex_index(self.radius, pos) {
+            Some(i) => self.bits[i / 64] & (1 << (i % 64)) != 0,
+            None => false
+        }
+    }
+
+    /// Returns whether `pos` was added, which it isn't if it already was in
+    /// the set or is off the field.
+    pub fn insert(&mut self, pos: Position) -> bool {
+        match board::hex_index(self.radius, pos) {
+            Some(i) if self.bits[i / 64] & (1 << (i % 64)) == 0 => {
+                self.bits[i / 64] |= 1 << (i % 64);
+                self.len += 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    pub fn remove(&mut self, pos: Position) -> bool {
+        match board::hex_index(self.radius, pos) {
+            Some(i) if self.bits[i / 64] & (1 << (i % 64)) != 0 => {
+                self.bits[i / 64] &= !(1 << (i % 64));
+                self.len -= 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for word in self.bits.iter_mut() {
+            *word = 0;
+        }
+        self.len = 0;
+    }
+
+    /// The hexes in the set, in board index order.
+    pub fn positions(&self) -> Vec<Position> {
+        let mut ret = Vec::with_capacity(self.len);
+        let mut i = 0;
+        for x in -self.radius..self.radius + 1 {
+            for y in board::column_start(x, self.radius)..board::column_end(x, self.radius) + 1 {
+                if self.bits[i / 64] & (1 << (i % 64)) != 0 {
+                    ret.push(Position { x: x, y: y });
+                }
+                i += 1;
+            }
+        }
+        ret
+    }
+
+    fn combine<F: Fn(u64, u64) -> u64>(&mut self, other: &HexSet, op: F) {
+        assert!(self.radius == other.radius, "can't combine sets of fields with different radii");
+        let mut len = 0;
+        for (word, &other_word) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word = op(*word, other_word);
+            len += word.count_ones() as usize;
+        }
+        self.len = len;
+    }
+
+    pub fn union_with(&mut self, other: &HexSet) {
+        self.combine(other, |a, b| a | b);
+    }
+
+    pub fn intersect_with(&mut self, other: &HexSet) {
+        self.combine(other, |a, b| a & b);
+    }
+
+    pub fn difference_with(&mut self, other: &HexSet) {
+        self.combine(other, |a, b| a & !b);
+    }
+
+    #[allow(dead_code)]
+    pub fn union(&self, other: &HexSet) -> HexSet {
+        let mut ret = self.clone();
+        ret.union_with(other);
+        ret
+    }
+
+    #[allow(dead_code)]
+    pub fn intersection(&self, other: &HexSet) -> HexSet {
+        let mut ret = self.clone();
+        ret.intersect_with(other);
+        ret
+    }
+
+    #[allow(dead_code)]
+    pub fn difference(&self, other: &HexSet) -> HexSet {
+        let mut ret = self.clone();
+        ret.difference_with(other);
+        ret
+    }
+}
+
+/// A value for some of the hexes on a field of a given radius, stored in a
+/// dense array. Hexes off the field can't have a value.
+#[derive(Debug, Clone)]
+pub struct HexMap<T> {
+    radius: i32,
+    values: Vec<Option<T>>,
+    len: usize
+}
+
+impl<T> HexMap<T> {
+    pub fn new(radius: i32) -> HexMap<T> {
+        let count = board::hex_count(radius);
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            values.push(None);
+        }
+        HexMap { radius: radius, values: values, len: 0 }
+    }
+
+    pub fn for_board(board: &Board) -> HexMap<T> {
+        HexMap::new(board.radius())
+    }
+
+    #[allow(dead_code)]
+    pub fn radius(&self) -> i32 {
+        self.radius
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains_key(&self, pos: Position) -> bool {
+        self.get(pos).is_some()
+    }
+
+    pub fn get(&self, pos: Position) -> Option<&T> {
+        board::hex_index(self.radius, pos).and_then(|i| self.values[i].as_ref())
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mut(&mut self, pos: Position) -> Option<&mut T> {
+        match board::hex_index(self.radius, pos) {
+            Some(i) => self.values[i].as_mut(),
+            None => None
+        }
+    }
+
+    /// Returns the value `pos` had before. Values for hexes off the field are
+    /// handed straight back as `Err`.
+    pub fn insert(&mut self, pos: Position, value: T) -> Result<Option<T>, T> {
+        match board::hex_index(self.radius, pos) {
+            Some(i) => {
+                let old = mem::replace(&mut self.values[i], Some(value));
+                if old.is_none() {
+                    self.len += 1;
+                }
+                Ok(old)
+            },
+            None => Err(value)
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn remove(&mut self, pos: Position) -> Option<T> {
+        match board::hex_index(self.radius, pos) {
+            Some(i) => {
+                let old = self.values[i].take();
+                if old.is_some() {
+                    self.len -= 1;
+                }
+                old
+            },
+            None => None
+        }
+    }
+
+    /// The hexes that have a value, as a set.
+    pub fn keys(&self) -> HexSet {
+        let mut set = HexSet::new(self.radius);
+        for (i, value) in self.values.iter().enumerate() {
+            if value.is_some() {
+                set.bits[i / 64] |= 1 << (i % 64);
+                set.len += 1;
+            }
+        }
+        set
+    }
+}
+
+#[test]
+fn test_hexset() {
+    let board = Board::with_radius(6);
+    let mut set = HexSet::for_board(&board);
+    assert!(set.insert(Position { x: 6, y: 0 }));
+    assert!(!set.insert(Position { x: 6, y: 0 }));
+    assert!(!set.insert(Position { x: 7, y: 0 }));
+    assert!(set.len() == 1 && set.contains(Position { x: 6, y: 0 }));
+    assert!(set.remove(Position { x: 6, y: 0 }) && set.is_empty());
+
+    // every hex of the board fits, and comes back out in index order
+    let full = HexSet::from_positions(6, board.hexes().iter().cloned());
+    assert!(full.len() == board.len());
+    assert!(&full.positions()[..] == board.hexes());
+
+    let a = HexSet::from_positions(6, Position { x: 0, y: 0 }.positions_within(2));
+    let b = HexSet::from_positions(6, Position { x: 2, y: 0 }.positions_within(2));
+    let both = a.intersection(&b);
+    assert!(both.positions().iter().all(|pos| a.contains(*pos) && b.contains(*pos)));
+    assert!(a.union(&b).len() == a.len() + b.len() - both.len());
+    assert!(a.difference(&b).len() == a.len() - both.len());
+    assert!(a.difference(&b).intersection(&b).is_empty());
+}
+
+#[test]
+fn test_hexmap() {
+    let mut map: HexMap<u32> = HexMap::new(4);
+    assert!(map.insert(Position { x: 1, y: 2 }, 5) == Ok(None));
+    assert!(map.insert(Position { x: 1, y: 2 }, 6) == Ok(Some(5)));
+    assert!(map.insert(Position { x: 9, y: 0 }, 7) == Err(7));
+    *map.get_mut(Position { x: 1, y: 2 }).unwrap() += 1;
+    assert!(map.get(Position { x: 1, y: 2 }) == Some(&7));
+    assert!(map.len() == 1 && map.keys().contains(Position { x: 1, y: 2 }));
+    assert!(map.remove(Position { x: 1, y: 2 }) == Some(7));
+    assert!(map.is_empty() && !map.contains_key(Position { x: 1, y: 2 }));
+}
+
+#[bench]
+fn bench_hexset_fill_and_query_radius_50(b: &mut Bencher) {
+    let hexes = Board::with_radius(50).hexes().to_vec();
+    b.iter(|| {
+        let mut set = HexSet::new(50);
+        for pos in hexes.iter() {
+            set.insert(*pos);
+        }
+        hexes.iter().filter(|pos| set.contains(**pos)).count()
+    });
+}
+
+#[bench]
+fn bench_vec_fill_and_query_radius_50(b: &mut Bencher) {
+    // the Vec with contains that the AI used before, for comparison
+    let hexes = Board::with_radius(50).hexes().to_vec();
+    b.iter(|| {
+        let mut seen = Vec::new();
+        for pos in hexes.iter() {
+            if !seen.contains(pos) {
+                seen.push(*pos);
+            }
+        }
+        hexes.iter().filter(|pos| seen.contains(*pos)).count()
+    });
+}
@@ -18,67 +18,627 @@
  */
 use super::{Position, GameConfig};
 use serde::json::{self, Value};
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+#[cfg(test)] use test::Bencher;
 
-macro_rules! getString {
-    ($obj:ident, $field:expr) => {
-        String::from_str($obj.get($field).unwrap().as_string().unwrap())
+/// Everything that can go wrong while turning a server frame into a `Message`.
+#[derive(Debug)]
+pub enum ParseError {
+    InvalidJson(json::Error),
+    MissingField(&'static str),
+    WrongType(&'static str)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidJson(ref e) => write!(f, "invalid JSON: {:?}", e),
+            ParseError::MissingField(field) => write!(f, "missing field `{}`", field),
+            ParseError::WrongType(field) => write!(f, "field `{}` has the wrong type", field)
+        }
     }
 }
 
-pub fn parse_message(msg: String) -> Message {
-    let value: Value = json::from_str(&msg).unwrap();
-    let obj = value.as_object().unwrap();
-    let message = match getString!(obj, "type").as_ref() {
-        "connected" => Message::ConnectedMessage(json::from_str(&msg).unwrap()),
-        "start" => Message::StartMessage(json::from_str(&msg).unwrap()),
-        "end" => Message::EndMessage(json::from_str(&msg).unwrap()),
-        "events" => Message::EventsMessage(parse_events_message(&value)),
-        _ => panic!("Couldn't parse message")
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseError::InvalidJson(_) => "invalid JSON",
+            ParseError::MissingField(_) => "missing field",
+            ParseError::WrongType(_) => "field has the wrong type"
+        }
+    }
+}
+
+/// Every field any server message has, typed. A frame is deserialized into
+/// this in one pass and then turned into the `Message` its `type` asks for,
+/// without building a `Value` tree first.
+#[derive(Debug, Deserialize)]
+struct WireMessage {
+    #[serde(rename="type")] kind: Option<String>,
+    #[serde(rename="teamId")] team_id: Option<u32>,
+    #[serde(rename="roundId")] round_id: Option<u32>,
+    #[serde(rename="winnerTeamId")] winner_team_id: Option<u32>,
+    config: Option<GameConfig>,
+    you: Option<Team>,
+    #[serde(rename="otherTeams")] other_teams: Option<Vec<TeamNoPosNoHp>>,
+    events: Option<Vec<WireEvent>>
+}
+
+/// Every field any event has, typed, like `WireMessage`.
+#[derive(Debug, Deserialize)]
+struct WireEvent {
+    #[serde(rename="event")] kind: Option<String>,
+    #[serde(rename="botId")] bot_id: Option<u32>,
+    damage: Option<u32>,
+    source: Option<u32>,
+    pos: Option<Position>
+}
+
+/// The `type` tags `message_from_wire` knows.
+const MESSAGE_KINDS: &'static [&'static str] = &["connected", "start", "end", "events"];
+/// The `event` tags `event_from_wire` knows.
+const EVENT_KINDS: &'static [&'static str] = &[
+    "damaged", "hit", "die", "see", "radarEcho", "detected", "noaction", "move", "seeAsteroid"
+];
+
+fn required<T>(field: Option<T>, name: &'static str) -> Result<T, ParseError> {
+    field.ok_or(ParseError::MissingField(name))
+}
+
+/// Deserializes a server frame in one pass and dispatches on its `type` tag.
+/// A frame the typed pass can't take, which may just be an unknown message
+/// or event reusing a known field name for something else, is looked at
+/// again tag by tag with `parse_value`.
+pub fn parse_message(msg: &str) -> Result<Message, ParseError> {
+    let mut wire: WireMessage = match json::from_str(msg) {
+        Ok(wire) => wire,
+        Err(e) => return parse_value(msg, e)
     };
-    message
+    let events = match wire.events.take() {
+        Some(wire_events) => {
+            let mut events = Vec::with_capacity(wire_events.len());
+            for event in wire_events.iter() {
+                events.push(try!(event_from_wire(event)));
+            }
+            if events.iter().any(|event| match *event { Event::Unknown { .. } => true, _ => false }) {
+                try!(keep_unknown_events(msg, &mut events));
+            }
+            Some(events)
+        },
+        None => None
+    };
+    message_from_wire(wire, events, msg)
 }
 
-fn parse_events_message(value: &Value) -> EventsMessage {
-    let obj = value.as_object().unwrap();
-    EventsMessage {
-        type_: getString!(obj, "type"),
-        round_id: obj.get("roundId").unwrap().as_u64().unwrap() as u32,
-        config: json::value::from_value(obj.get("config").unwrap().clone()).unwrap(),
-        you: json::value::from_value(obj.get("you").unwrap().clone()).unwrap(),
-        other_teams: json::value::from_value(obj.get("otherTeams").unwrap().clone()).unwrap(),
-        events: obj.get("events").unwrap().clone().as_array().unwrap().iter().map(|ev| parse_event(ev.clone())).collect()
+/// Turns the deserialized frame into the `Message` its `type` asks for, with
+/// the `events` already converted.
+fn message_from_wire(wire: WireMessage, events: Option<Vec<Event>>, msg: &str) -> Result<Message, ParseError> {
+    let kind = try!(required(wire.kind, "type"));
+    let message = match kind.as_ref() {
+        "connected" => Message::ConnectedMessage(ConnectedMessage {
+            team_id: try!(required(wire.team_id, "teamId")),
+            config: try!(required(wire.config, "config"))
+        }),
+        "start" => Message::StartMessage(StartMessage {
+            config: try!(required(wire.config, "config")),
+            you: try!(required(wire.you, "you")),
+            other_teams: try!(required(wire.other_teams, "otherTeams"))
+        }),
+        "end" => Message::EndMessage(EndMessage {
+            winner_team_id: wire.winner_team_id,
+            you: try!(required(wire.you, "you"))
+        }),
+        "events" => Message::EventsMessage(EventsMessage {
+            round_id: try!(required(wire.round_id, "roundId")),
+            config: try!(required(wire.config, "config")),
+            you: try!(required(wire.you, "you")),
+            other_teams: try!(required(wire.other_teams, "otherTeams")),
+            events: try!(required(events, "events"))
+        }),
+        _ => Message::Unknown { kind: kind.clone(), raw: try!(json::from_str(msg).map_err(ParseError::InvalidJson)) }
+    };
+    Ok(message)
+}
+
+/// Turns a deserialized entry of the `events` array into an `Event`. The
+/// `raw` of an unknown event is filled in by the caller.
+fn event_from_wire(event: &WireEvent) -> Result<Event, ParseError> {
+    let kind = try!(required(event.kind.as_ref(), "event"));
+    let parsed = match &kind[..] {
+        "damaged" => Event::DamagedEvent(DamagedEvent {
+            bot_id: try!(required(event.bot_id, "botId")),
+            damage: try!(required(event.damage, "damage"))
+        }),
+        "hit" => Event::HitEvent(HitEvent {
+            bot_id: try!(required(event.bot_id, "botId")),
+            source: try!(required(event.source, "source"))
+        }),
+        "die" => Event::DieEvent(DieEvent {
+            bot_id: try!(required(event.bot_id, "botId"))
+        }),
+        "see" => Event::SeeEvent(SeeEvent {
+            bot_id: try!(required(event.bot_id, "botId")),
+            source: try!(required(event.source, "source")),
+            pos: try!(required(event.pos, "pos"))
+        }),
+        "radarEcho" => Event::RadarEchoEvent(RadarEchoEvent {
+            pos: try!(required(event.pos, "pos"))
+        }),
+        "detected" => Event::DetectedEvent(DetectedEvent {
+            bot_id: try!(required(event.bot_id, "botId"))
+        }),
+        "noaction" => Event::NoActionEvent(NoActionEvent {
+            bot_id: try!(required(event.bot_id, "botId"))
+        }),
+        "move" => Event::MoveEvent(MoveEvent {
+            bot_id: try!(required(event.bot_id, "botId")),
+            pos: try!(required(event.pos, "pos"))
+        }),
+        "seeAsteroid" => Event::SeeAsteroidEvent(SeeAsteroidEvent {
+            pos: try!(required(event.pos, "pos"))
+        }),
+        _ => Event::Unknown { kind: kind.clone(), raw: Value::Null }
+    };
+    Ok(parsed)
+}
+
+/// Fills in the `raw` of the unknown events in `events` from the frame.
+fn keep_unknown_events(msg: &str, events: &mut Vec<Event>) -> Result<(), ParseError> {
+    let value: Value = try!(json::from_str(msg).map_err(ParseError::InvalidJson));
+    let raw_events = match value.find("events").and_then(|events| events.as_array()) {
+        Some(raw_events) => raw_events,
+        None => return Err(ParseError::WrongType("events"))
+    };
+    for (event, raw_event) in events.iter_mut().zip(raw_events.iter()) {
+        if let Event::Unknown { ref mut raw, .. } = *event {
+            *raw = raw_event.clone();
+        }
     }
+    Ok(())
 }
 
-fn parse_event(value: Value) -> Event {
-    let value1 = value.clone();
-    let obj = value1.as_object().unwrap();
-    match getString!(obj, "event").as_ref() {
-        "damaged" => Event::DamagedEvent(json::value::from_value(value).unwrap()),
-        "hit" => Event::HitEvent(json::value::from_value(value).unwrap()),
-        "die" => Event::DieEvent(json::value::from_value(value).unwrap()),
-        "see" => Event::SeeEvent(json::value::from_value(value).unwrap()),
-        "radarEcho" => Event::RadarEchoEvent(json::value::from_value(value).unwrap()),
-        "detected" => Event::DetectedEvent(json::value::from_value(value).unwrap()),
-        "noaction" => Event::NoActionEvent(json::value::from_value(value).unwrap()),
-        "move" => Event::MoveEvent(json::value::from_value(value).unwrap()),
-        "seeAsteroid" => Event::SeeAsteroidEvent(json::value::from_value(value).unwrap()),
-        _ => panic!("Couldn't parse event")
+/// The tag `name` of an object, which has to be there and be a string.
+fn tag_of(value: &Value, name: &'static str) -> Result<String, ParseError> {
+    let obj = match value.as_object() {
+        Some(obj) => obj,
+        None => return Err(ParseError::WrongType(if name == "type" { "message" } else { "event" }))
+    };
+    match obj.get(name) {
+        Some(tag) => tag.as_string().map(|tag| tag.to_string()).ok_or(ParseError::WrongType(name)),
+        None => Err(ParseError::MissingField(name))
     }
 }
 
+/// The slow path of `parse_message`: reads the frame as a `Value`, keeps
+/// unknown messages and events as they are and only deserializes the known
+/// ones into their typed fields.
+fn parse_value(msg: &str, error: json::Error) -> Result<Message, ParseError> {
+    let mut value: Value = match json::from_str(msg) {
+        Ok(value) => value,
+        Err(_) => return Err(ParseError::InvalidJson(error))
+    };
+    let kind = try!(tag_of(&value, "type"));
+    if !MESSAGE_KINDS.iter().any(|known| kind == *known) {
+        return Ok(Message::Unknown { kind: kind, raw: value });
+    }
+    let raw_events = match value {
+        Value::Object(ref mut obj) => obj.remove("events"),
+        _ => None
+    };
+    let wire: WireMessage = try!(json::value::from_value(value.clone()).map_err(|_| wrong_type(&value, "message")));
+    let events = match raw_events {
+        Some(Value::Array(raw_events)) => {
+            let mut events = Vec::with_capacity(raw_events.len());
+            for raw_event in raw_events.into_iter() {
+                events.push(try!(parse_value_event(raw_event)));
+            }
+            Some(events)
+        },
+        Some(_) => return Err(ParseError::WrongType("events")),
+        None => None
+    };
+    message_from_wire(wire, events, msg)
+}
+
+/// Converts a single entry of the `events` array on the slow path,
+/// dispatching on its `event` tag before looking at any other field.
+fn parse_value_event(value: Value) -> Result<Event, ParseError> {
+    let kind = try!(tag_of(&value, "event"));
+    if !EVENT_KINDS.iter().any(|known| kind == *known) {
+        return Ok(Event::Unknown { kind: kind, raw: value });
+    }
+    let wire: WireEvent = try!(json::value::from_value(value.clone()).map_err(|_| wrong_type(&value, "event")));
+    event_from_wire(&wire)
+}
+
+fn fits<T: Deserialize>(value: &Value) -> bool {
+    json::value::from_value::<T>(value.clone()).is_ok()
+}
+
+/// The error for an object of a known kind that didn't deserialize: the
+/// first of its fields that doesn't have the type it should, or `whole`.
+fn wrong_type(value: &Value, whole: &'static str) -> ParseError {
+    let obj = match value.as_object() {
+        Some(obj) => obj,
+        None => return ParseError::WrongType(whole)
+    };
+    for (name, value) in obj.iter() {
+        let (field, ok) = match name.as_ref() {
+            "teamId" => ("teamId", fits::<u32>(value)),
+            "roundId" => ("roundId", fits::<u32>(value)),
+            "winnerTeamId" => ("winnerTeamId", fits::<Option<u32>>(value)),
+            "config" => ("config", fits::<GameConfig>(value)),
+            "you" => ("you", fits::<Team>(value)),
+            "otherTeams" => ("otherTeams", fits::<Vec<TeamNoPosNoHp>>(value)),
+            "botId" => ("botId", fits::<u32>(value)),
+            "damage" => ("damage", fits::<u32>(value)),
+            "source" => ("source", fits::<u32>(value)),
+            "pos" => ("pos", fits::<Position>(value)),
+            _ => continue
+        };
+        if !ok {
+            return ParseError::WrongType(field);
+        }
+    }
+    ParseError::WrongType(whole)
+}
+
+/// Serializes `value` with its tag added as the field `name`.
+fn with_tag<T: Serialize>(value: &T, name: &str, tag: &str) -> Value {
+    let mut value = json::value::to_value(value);
+    if let Value::Object(ref mut obj) = value {
+        obj.insert(name.to_string(), Value::String(tag.to_string()));
+    }
+    value
+}
+
+impl Event {
+    /// The `event` tag the server uses for this kind of event.
+    pub fn kind(&self) -> &str {
+        match *self {
+            Event::DamagedEvent(_) => "damaged",
+            Event::HitEvent(_) => "hit",
+            Event::DieEvent(_) => "die",
+            Event::SeeEvent(_) => "see",
+            Event::RadarEchoEvent(_) => "radarEcho",
+            Event::DetectedEvent(_) => "detected",
+            Event::NoActionEvent(_) => "noaction",
+            Event::MoveEvent(_) => "move",
+            Event::SeeAsteroidEvent(_) => "seeAsteroid",
+            Event::Unknown { ref kind, .. } => &kind[..]
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        let kind = self.kind();
+        match *self {
+            Event::DamagedEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::HitEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::DieEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::SeeEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::RadarEchoEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::DetectedEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::NoActionEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::MoveEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::SeeAsteroidEvent(ref ev) => with_tag(ev, "event", kind),
+            Event::Unknown { ref raw, .. } => raw.clone()
+        }
+    }
+}
+
+impl Message {
+    /// Serializes the message the way the server sends it, the inverse of `parse_message`.
+    pub fn to_json(&self) -> String {
+        let value = match *self {
+            Message::ConnectedMessage(ref msg) => with_tag(msg, "type", "connected"),
+            Message::StartMessage(ref msg) => with_tag(msg, "type", "start"),
+            Message::EndMessage(ref msg) => with_tag(msg, "type", "end"),
+            Message::EventsMessage(ref msg) => {
+                let mut obj = BTreeMap::new();
+                obj.insert("type".to_string(), Value::String("events".to_string()));
+                obj.insert("roundId".to_string(), Value::U64(msg.round_id as u64));
+                obj.insert("config".to_string(), json::value::to_value(&msg.config));
+                obj.insert("you".to_string(), json::value::to_value(&msg.you));
+                obj.insert("otherTeams".to_string(), json::value::to_value(&msg.other_teams));
+                obj.insert("events".to_string(), Value::Array(msg.events.iter().map(|ev| ev.to_value()).collect()));
+                Value::Object(obj)
+            },
+            Message::Unknown { ref raw, .. } => raw.clone()
+        };
+        json::to_string(&value).unwrap()
+    }
+}
+
+#[cfg(test)]
+fn events_frame_with(event: &str) -> String {
+    let mut value: Value = json::from_str(include_str!("fixtures/events.json")).unwrap();
+    if let Value::Object(ref mut obj) = value {
+        if let Some(&mut Value::Array(ref mut events)) = obj.get_mut("events") {
+            events.push(json::from_str(event).unwrap());
+        }
+    }
+    json::to_string(&value).unwrap()
+}
+
 #[test]
 fn test_parse_event() {
     let event_json = "{\"event\":\"noaction\",\"botId\":4}".to_string();
-    let event_value: Value = json::from_str(&event_json).unwrap();
-    let event_struct = parse_event(event_value);
+    let event_struct = match parse_message(&events_frame_with(&event_json)).unwrap() {
+        Message::EventsMessage(msg) => msg.events.into_iter().last().unwrap(),
+        _ => panic!("Test failed")
+    };
     match event_struct {
         Event::NoActionEvent(ev) => assert!(ev.bot_id == 4),
         _ => panic!("Test failed")
     }
 }
 
-#[derive(Debug, Deserialize, Default, Clone)]
+#[test]
+fn test_parse_message_errors() {
+    match parse_message("{\"type\":") {
+        Err(ParseError::InvalidJson(_)) => (),
+        other => panic!("Expected invalid JSON, got {:?}", other)
+    }
+    match parse_message("{\"type\":\"connected\",\"teamId\":\"one\"}") {
+        Err(ParseError::WrongType(field)) => assert!(field == "teamId"),
+        other => panic!("Expected wrong type, got {:?}", other)
+    }
+    match parse_message("{\"type\":\"end\"}") {
+        Err(ParseError::MissingField(field)) => assert!(field == "you"),
+        other => panic!("Expected missing field, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_event_unknown_type() {
+    let event = match parse_message(&events_frame_with("{\"event\":\"teleport\",\"botId\":4}")) {
+        Ok(Message::EventsMessage(msg)) => msg.events.into_iter().last(),
+        other => panic!("Expected events message, got {:?}", other)
+    };
+    match event {
+        Some(Event::Unknown { ref kind, ref raw }) => {
+            assert!(kind == "teleport");
+            assert!(raw.find("botId").and_then(|v| v.as_u64()) == Some(4));
+        },
+        other => panic!("Expected unknown event, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_message_unknown_event_reusing_field_names() {
+    let frame = events_frame_with("{\"event\":\"shield\",\"damage\":\"full\",\"pos\":[1,2]}");
+    let msg = match parse_message(&frame) {
+        Ok(Message::EventsMessage(msg)) => msg,
+        other => panic!("Expected events message, got {:?}", other)
+    };
+    assert!(msg.events.len() == 10);
+    match msg.events[9] {
+        Event::Unknown { ref kind, ref raw } => {
+            assert!(kind == "shield");
+            assert!(raw.find("damage").and_then(|v| v.as_string()) == Some("full"));
+        },
+        ref other => panic!("Expected unknown event, got {:?}", other)
+    }
+    match msg.events[0] {
+        Event::DamagedEvent(ref ev) => assert!(ev.damage == 2),
+        ref other => panic!("Expected damaged event, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_message_known_event_wrong_type() {
+    match parse_message(&events_frame_with("{\"event\":\"damaged\",\"botId\":1,\"damage\":\"full\"}")) {
+        Err(ParseError::WrongType(field)) => assert!(field == "damage"),
+        other => panic!("Expected wrong type, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_message_unknown_type_reusing_field_names() {
+    match parse_message("{\"type\":\"pause\",\"config\":\"later\",\"roundId\":[3]}") {
+        Ok(Message::Unknown { ref kind, ref raw }) => {
+            assert!(kind == "pause");
+            assert!(raw.find("config").and_then(|v| v.as_string()) == Some("later"));
+        },
+        other => panic!("Expected unknown message, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_message_unknown_type() {
+    match parse_message("{\"type\":\"bogus\",\"roundId\":3}") {
+        Ok(Message::Unknown { ref kind, .. }) => assert!(kind == "bogus"),
+        other => panic!("Expected unknown message, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_fixture_connected() {
+    match parse_message(include_str!("fixtures/connected.json")).unwrap() {
+        Message::ConnectedMessage(msg) => {
+            assert!(msg.team_id == 1);
+            assert!(msg.config.bots == 3);
+            assert!(msg.config.field_radius == 14);
+            assert!(msg.config.move_ == 2);
+            assert!(msg.config.start_hp == 10);
+            assert!(msg.config.cannon == 1);
+            assert!(msg.config.radar == 3);
+            assert!(msg.config.see == 2);
+            assert!(msg.config.max_count == 200);
+            assert!(msg.config.loop_time == 300);
+            assert!(msg.config.asteroids == Some(40));
+        },
+        other => panic!("Expected connected message, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_fixture_start() {
+    match parse_message(include_str!("fixtures/start.json")).unwrap() {
+        Message::StartMessage(msg) => {
+            assert!(msg.config.field_radius == 14);
+            assert!(msg.you.name == "Ferrous Bueller");
+            assert!(msg.you.team_id == 1);
+            assert!(msg.you.bots.len() == 3);
+            assert!(msg.you.bots[1].name == "Cameron");
+            assert!(msg.you.bots[1].hp == 10);
+            assert!(msg.you.bots[1].alive);
+            assert!(msg.you.bots[1].pos == Position { x: -7, y: 11 });
+            assert!(msg.other_teams.len() == 1);
+            assert!(msg.other_teams[0].name == "Rooney");
+            assert!(msg.other_teams[0].team_id == 2);
+            assert!(msg.other_teams[0].bots[2].bot_id == 5);
+            assert!(msg.other_teams[0].bots[2].hp == None);
+            assert!(msg.other_teams[0].bots[2].pos == None);
+        },
+        other => panic!("Expected start message, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_fixture_events() {
+    let msg = match parse_message(include_str!("fixtures/events.json")).unwrap() {
+        Message::EventsMessage(msg) => msg,
+        other => panic!("Expected events message, got {:?}", other)
+    };
+    assert!(msg.round_id == 42);
+    assert!(msg.config.asteroids == Some(40));
+    assert!(msg.you.bots[0].hp == 8);
+    assert!(!msg.you.bots[1].alive);
+    assert!(!msg.other_teams[0].bots[1].alive);
+    assert!(msg.events.len() == 9);
+    let mut events = msg.events.into_iter();
+    match events.next() {
+        Some(Event::DamagedEvent(ev)) => assert!(ev.bot_id == 0 && ev.damage == 2),
+        other => panic!("Expected damaged event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::HitEvent(ev)) => assert!(ev.bot_id == 4 && ev.source == 2),
+        other => panic!("Expected hit event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::DieEvent(ev)) => assert!(ev.bot_id == 1),
+        other => panic!("Expected die event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::SeeEvent(ev)) => assert!(ev.bot_id == 3 && ev.source == 0 && ev.pos == Position { x: -4, y: 6 }),
+        other => panic!("Expected see event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::RadarEchoEvent(ev)) => assert!(ev.pos == Position { x: 3, y: -1 }),
+        other => panic!("Expected radarEcho event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::DetectedEvent(ev)) => assert!(ev.bot_id == 2),
+        other => panic!("Expected detected event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::NoActionEvent(ev)) => assert!(ev.bot_id == 2),
+        other => panic!("Expected noaction event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::MoveEvent(ev)) => assert!(ev.bot_id == 0 && ev.pos == Position { x: -5, y: 8 }),
+        other => panic!("Expected move event, got {:?}", other)
+    }
+    match events.next() {
+        Some(Event::SeeAsteroidEvent(ev)) => assert!(ev.pos == Position { x: -3, y: 8 }),
+        other => panic!("Expected seeAsteroid event, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_fixture_end() {
+    match parse_message(include_str!("fixtures/end.json")).unwrap() {
+        Message::EndMessage(msg) => {
+            assert!(msg.winner_team_id == Some(1));
+            assert!(msg.you.team_id == 1);
+            assert!(msg.you.bots[2].hp == 6);
+        },
+        other => panic!("Expected end message, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_fixture_end_without_winner() {
+    match parse_message(include_str!("fixtures/end_no_winner.json")).unwrap() {
+        Message::EndMessage(msg) => {
+            assert!(msg.winner_team_id == None);
+            assert!(msg.you.bots.iter().all(|bot| !bot.alive));
+        },
+        other => panic!("Expected end message, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_fixtures_round_trip() {
+    let fixtures = [
+        include_str!("fixtures/connected.json"),
+        include_str!("fixtures/start.json"),
+        include_str!("fixtures/events.json"),
+        include_str!("fixtures/end.json"),
+        include_str!("fixtures/end_no_winner.json")
+    ];
+    for fixture in fixtures.iter() {
+        let once = parse_message(fixture).unwrap().to_json();
+        let twice = parse_message(&once).unwrap().to_json();
+        assert!(once == twice);
+        let original: Value = json::from_str(fixture).unwrap();
+        let serialized: Value = json::from_str(&once).unwrap();
+        assert!(original.find("type") == serialized.find("type"));
+        assert!(original.find("events").map(|events| events.as_array().unwrap().len()) ==
+                serialized.find("events").map(|events| events.as_array().unwrap().len()));
+    }
+}
+
+#[bench]
+fn bench_parse_events_frame(b: &mut Bencher) {
+    let frame = include_str!("fixtures/events_large.json");
+    b.iter(|| parse_message(frame).unwrap());
+}
+
+/// The parser this module started out with, minus its panics: the frame is
+/// parsed into a `Value`, every subtree is cloned out of it before it is
+/// converted and every event is cloned twice more.
+#[cfg(test)]
+fn baseline_parse_events_message(msg: &str) -> EventsMessage {
+    fn baseline_parse_event(value: Value) -> Event {
+        let value1 = value.clone();
+        let obj = value1.as_object().unwrap();
+        match obj.get("event").unwrap().as_string().unwrap() {
+            "damaged" => Event::DamagedEvent(json::value::from_value(value).unwrap()),
+            "hit" => Event::HitEvent(json::value::from_value(value).unwrap()),
+            "die" => Event::DieEvent(json::value::from_value(value).unwrap()),
+            "see" => Event::SeeEvent(json::value::from_value(value).unwrap()),
+            "radarEcho" => Event::RadarEchoEvent(json::value::from_value(value).unwrap()),
+            "detected" => Event::DetectedEvent(json::value::from_value(value).unwrap()),
+            "noaction" => Event::NoActionEvent(json::value::from_value(value).unwrap()),
+            "move" => Event::MoveEvent(json::value::from_value(value).unwrap()),
+            "seeAsteroid" => Event::SeeAsteroidEvent(json::value::from_value(value).unwrap()),
+            kind => Event::Unknown { kind: kind.to_string(), raw: value }
+        }
+    }
+    let value: Value = json::from_str(msg).unwrap();
+    let obj = value.as_object().unwrap();
+    EventsMessage {
+        round_id: obj.get("roundId").unwrap().as_u64().unwrap() as u32,
+        config: json::value::from_value(obj.get("config").unwrap().clone()).unwrap(),
+        you: json::value::from_value(obj.get("you").unwrap().clone()).unwrap(),
+        other_teams: json::value::from_value(obj.get("otherTeams").unwrap().clone()).unwrap(),
+        events: obj.get("events").unwrap().clone().as_array().unwrap().iter().map(|ev| baseline_parse_event(ev.clone())).collect()
+    }
+}
+
+#[bench]
+fn bench_parse_events_frame_baseline(b: &mut Bencher) {
+    let frame = include_str!("fixtures/events_large.json");
+    b.iter(|| baseline_parse_events_message(frame));
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Bot {
     #[serde(rename="botId")] pub bot_id: u32,
     pub name: String,
@@ -88,7 +648,7 @@ pub struct Bot {
     pub pos: Position
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct BotNoPosNoHp {
     #[serde(rename="botId")] pub bot_id: u32,
     pub name: String,
@@ -98,76 +658,67 @@ pub struct BotNoPosNoHp {
     pub pos: Option<Position>
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Team {
     pub name: String,
     #[serde(rename="teamId")] pub team_id: u32,
     pub bots: Vec<Bot>
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct TeamNoPosNoHp {
     pub name: String,
     #[serde(rename="teamId")] pub team_id: u32,
     pub bots: Vec<BotNoPosNoHp>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DamagedEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32,
     pub damage: u32
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct HitEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32,
     pub source: u32
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DieEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SeeEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32,
     pub source: u32,
     pub pos: Position
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RadarEchoEvent {
-    event: String,
     pub pos: Position
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DetectedEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct NoActionEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct MoveEvent {
-    event: String,
     #[serde(rename="botId")] pub bot_id: u32,
     pub pos: Position
 }
 
-#[derive(Debug, Deserialize)]
-pub struct SeeAsteroidEvent{
-    event: String,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeeAsteroidEvent {
     pub pos: Position
 }
 
@@ -182,34 +733,32 @@ pub enum Event {
     DetectedEvent(DetectedEvent),
     NoActionEvent(NoActionEvent),
     MoveEvent(MoveEvent),
-    SeeAsteroidEvent(SeeAsteroidEvent)
+    SeeAsteroidEvent(SeeAsteroidEvent),
+    /// An event kind this client doesn't know yet, kept as sent by the server
+    Unknown { kind: String, raw: Value }
 }
 
-#[derive(Debug, Deserialize)]
-struct ConnectedMessage {
-    #[serde(rename="type")] type_: String,
-    #[serde(rename="teamId")] team_id: u32,
-    config: GameConfig
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectedMessage {
+    #[serde(rename="teamId")] pub team_id: u32,
+    pub config: GameConfig
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StartMessage {
-    #[serde(rename="type")] type_: String,
     pub config: GameConfig,
     pub you: Team,
     #[serde(rename="otherTeams")] pub other_teams: Vec<TeamNoPosNoHp>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct EndMessage {
-    #[serde(rename="type")] type_: String,
     #[serde(rename="winnerTeamId")] pub winner_team_id: Option<u32>,
     pub you: Team
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EventsMessage {
-    #[serde(rename="type")] type_: String,
     #[serde(rename="roundId")] pub round_id: u32,
     pub config: GameConfig,
     pub you: Team,
@@ -222,5 +771,7 @@ pub enum Message {
     ConnectedMessage(ConnectedMessage),
     StartMessage(StartMessage),
     EndMessage(EndMessage),
-    EventsMessage(EventsMessage)
+    EventsMessage(EventsMessage),
+    /// A message type this client doesn't know yet, kept as sent by the server
+    Unknown { kind: String, raw: Value }
 }
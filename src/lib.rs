@@ -0,0 +1,69 @@
+#![feature(custom_derive, custom_attribute, plugin, collections, test)]
+#![plugin(serde_macros)]
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+extern crate websocket;
+extern crate serde;
+extern crate rustc_serialize;
+extern crate hyper;
+#[cfg(test)] extern crate test;
+
+pub mod incoming;
+pub mod outgoing;
+pub mod ai;
+pub mod validation;
+pub mod session;
+pub mod supervisor;
+pub mod watchdog;
+pub mod recorder;
+pub mod replay;
+pub mod rules;
+pub mod server;
+pub mod simulator;
+pub mod tournament;
+pub mod config;
+pub mod webgame;
+pub mod hex;
+pub mod board;
+pub mod hexset;
+pub mod pathfinding;
+pub mod tracker;
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct GameConfig {
+    bots: i32,
+    #[serde(rename="fieldRadius")] field_radius: i32,
+    #[serde(rename="move")] move_: u32,
+    #[serde(rename="startHp")] start_hp: i32,
+    cannon: i32,
+    radar: i32,
+    see: i32,
+    #[serde(rename="maxCount")] max_count: i32,
+    #[serde(rename="loopTime")] loop_time: i32,
+    asteroids: Option<i32>
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
+pub struct Position {
+    x: i32,
+    y: i32
+}
+
+impl Copy for Position {}
@@ -1,5 +1,3 @@
-#![feature(custom_derive, custom_attribute, plugin, collections, scoped)]
-#![plugin(serde_macros)]
 /** Rust Tyckiting client - A websocket client for a fight to kill all other bots
  *  Copyright Futurice Oy (2015)
  *
@@ -19,40 +17,27 @@
  *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-extern crate websocket;
-extern crate serde;
+extern crate tyckiting;
 extern crate rustc_serialize;
 extern crate docopt;
-extern crate hyper;
-
-mod incoming;
-mod ai;
-
-use std::thread;
-use std::cmp::{max, min};
-use std::io::Read;
-use std::error::Error;
-
-use websocket::{Receiver, Sender};
-use websocket::Message as WSMessage;
-use websocket::client::request::Url;
-use websocket::Client;
-
-use serde::json;
 
 use docopt::Docopt;
 
-use hyper::Client as HClient;
-
-use rustc_serialize::base64::{ToBase64, MIME};
-
-use incoming::Message;
-use ai::{Ai, Action};
+use tyckiting::{ai, replay, rules, session, supervisor, tournament, webgame};
+use tyckiting::validation::ValidationMode;
+use tyckiting::supervisor::Backoff;
+use tyckiting::watchdog::Watchdog;
+use tyckiting::recorder::Recorder;
+use tyckiting::session::SessionOptions;
+use tyckiting::config::{ConfigFile, Settings, Overrides};
 
 static USAGE: &'static str = "
 tyckiting-client - a base for your AI
 
-Usage: tyckiting-client [--host <host>] [--name <name>] [--ai <ai>] [(--port <port> | --webgame <spec>)]
+Usage: tyckiting-client [options] [--ai-opt <kv>]... [(--port <port> | --webgame <opponents> [--each] [--repeat <n>])]
+       tyckiting-client replay <frames> [--ai <ai>] [--ai-opt <kv>]... [--config <file>] [--seed <seed>] [--diff <sent>]
+       tyckiting-client tournament <ais>... [--games <n>] [--config <file>] [--seed <seed>]
+       tyckiting-client --list-ais
        tyckiting-client [-h]
 
 
@@ -62,286 +47,238 @@ Options:
   -P, --port <port>         Port to connect to
   -n, --name <name>         Team's name
   -a, --ai <ai>             Select AI
-  --webgame <spec>          Ask for a game, format user:pass:opponentname
+  --list-ais                List the available AIs and their parameters
+  --ai-opt <kv>             Set a parameter of the AI as key=value, see --list-ais
+  --config <file>           Read options from a TOML file instead of tyckiting.toml
+  --seed <seed>             Seed for the AI's random numbers, random if not given
+  --webgame <opponents>     Ask the web API for a game against a comma separated list of bots
+  --each                    Play a separate web game against each of the bots instead
+  --repeat <n>              Play the web games this many times, once if not given
+  --api-url <url>           Web API endpoint for games, http://<host>/api/new if not given
+  --validate <mode>         Check actions before sending them: report (the default) or repair
+  --retries <n>             Connection attempts before giving up, 5 if not given
+  --retry-delay <ms>        Delay before the first reconnect, doubled on each failure, 500 if not given
+  --keep-ai-state           Keep the AI's state when reconnecting instead of starting afresh
+  --margin <ms>             Part of the server's loop time not given to the AI, 50 if not given
+  --record <dir>            Write every frame sent and received to a JSONL file per game in <dir>
+  --diff <sent>             Compare the replayed actions with the actions in <sent>
+  --games <n>               Games played by every pair of AIs in a tournament [default: 10]
+
+The replay command feeds a file of server frames, one per line, to the AI and
+prints the actions it produces. Recordings made with --record work as both
+<frames> and <sent>.
+
+The tournament command plays every pair of the given AIs against each other
+on varying maps without a server and prints their standings and Elo ratings.
+
+Every client option except --config can also be set in the config file, with
+the option's name as the key and dashes replaced by underscores (name for
+--name, params.<ai> for --ai-opt), or in a TYCKITING_ environment variable
+named the same way: TYCKITING_HOST, TYCKITING_PORT, TYCKITING_NAME,
+TYCKITING_AI, TYCKITING_AI_OPTS, TYCKITING_RECORD, TYCKITING_WEBGAME,
+TYCKITING_EACH, TYCKITING_REPEAT, TYCKITING_API_URL, TYCKITING_VALIDATE,
+TYCKITING_RETRIES, TYCKITING_RETRY_DELAY, TYCKITING_KEEP_AI_STATE,
+TYCKITING_MARGIN and TYCKITING_SEED. The environment wins over the command
+line, which wins over the config file. The web API credentials are read only
+from TYCKITING_USER and TYCKITING_PASSWORD or the user and password keys of
+the config file.
+
+Exit status is 2 when the client gives up connecting.
 ";
 
 #[derive(RustcDecodable, Debug)]
 struct Args {
+    cmd_replay: bool,
+    cmd_tournament: bool,
+    arg_ais: Vec<String>,
+    flag_games: u32,
+    arg_frames: String,
+    flag_diff: Option<String>,
+    flag_seed: Option<u64>,
     flag_host: Option<String>,
     flag_port: Option<u32>,
     flag_name: Option<String>,
     flag_ai: Option<String>,
-    flag_webgame: Option<String>
-}
-
-#[derive(Serialize)]
-struct WebGameRequest {
-    bots: Vec<String>
-}
-
-#[derive(Deserialize)]
-struct WebGameResponse {
-    #[allow(dead_code)] status: String,
-    #[allow(dead_code)] #[serde(rename="gameId")] game_id: u32,
-    host: String,
-    port: u32
+    flag_list_ais: bool,
+    flag_ai_opt: Vec<String>,
+    flag_config: Option<String>,
+    flag_webgame: Option<String>,
+    flag_api_url: Option<String>,
+    flag_each: bool,
+    flag_repeat: Option<u32>,
+    flag_validate: Option<String>,
+    flag_retries: Option<u32>,
+    flag_retry_delay: Option<u32>,
+    flag_keep_ai_state: bool,
+    flag_margin: Option<u32>,
+    flag_record: Option<String>
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
-    let host = args.flag_host.unwrap_or_else(|| "localhost".to_string());
-    let team_name = args.flag_name.unwrap_or_else(|| "Ferrous Bueller".to_string());
-    let ai_name = args.flag_ai.unwrap_or_else(|| "random".to_string());
-
-    let (game_host, port) = match args.flag_webgame {
-        Some(webgame) => {
-            let parts: Vec<&str> = webgame.split(":").collect();
-            if parts.len() != 3 {
-                panic!("invalid webgame spec");
-            }
-            let (user, pass, opponent) = (parts[0], parts[1], parts[2]);
-            let auth = format!("{}:{}", user, pass).as_bytes().to_base64(MIME);
-            let mut client = HClient::new();
-            let request_body_string = json::to_string(&WebGameRequest { bots: vec![opponent.to_string()]}).unwrap();
-            let request_body: &str = request_body_string.as_ref();
-            let mut response = client.post(Url::parse(format!("http://{}/api/new", host).as_ref()).unwrap())
-                .header(hyper::header::Authorization(format!("Basic {}", auth)))
-                .body(request_body)
-                .send().unwrap();
-            let mut body = String::new();
-            response.read_to_string(&mut body).unwrap();
-            if !(response.status == hyper::status::StatusCode::Ok) { panic!("Couldn't login"); }
-            let game_response = json::from_str::<WebGameResponse>(body.as_ref()).unwrap_or_else(|e| {panic!("Didn't get proper response, got: {}, error was: {}", body, e)});
-            println!("You can spectate at http://{}:{}.", game_response.host, game_response.port);
-            (game_response.host, game_response.port)
-        },
-        None => (host, args.flag_port.unwrap_or_else(|| 3000u32))
-    };
+    if args.flag_list_ais {
+        ai::print_registry();
+        return;
+    }
+    let config_file = ConfigFile::find(args.flag_config.as_ref().map(|s| s.as_ref())).unwrap_or_else(|e| fail(e.to_string()));
+    let mut cli_ai_opts = Vec::new();
+    for opt in args.flag_ai_opt.iter() {
+        cli_ai_opts.push(ai::split_opt(opt).unwrap_or_else(|e| fail(e.to_string())));
+    }
+    let settings = Settings::from_env(config_file.as_ref(), Overrides {
+        host: args.flag_host,
+        port: args.flag_port,
+        team_name: args.flag_name,
+        ai: args.flag_ai,
+        ai_opts: cli_ai_opts,
+        record: args.flag_record,
+        webgame: args.flag_webgame,
+        api_url: args.flag_api_url,
+        // a flag that isn't given leaves the config file and environment alone
+        each: if args.flag_each { Some(true) } else { None },
+        repeat: args.flag_repeat,
+        validate: args.flag_validate,
+        retries: args.flag_retries,
+        retry_delay: args.flag_retry_delay,
+        keep_ai_state: if args.flag_keep_ai_state { Some(true) } else { None },
+        margin: args.flag_margin,
+        seed: args.flag_seed
+    }).unwrap_or_else(|e| fail(e.to_string()));
+    let ai_name = settings.ai.clone();
+    let seed = settings.seed.unwrap_or_else(ai::random_seed);
+    println!("Using seed {}", seed);
+
+    if args.cmd_replay {
+        let (entry, params) = resolve_ai(&ai_name, &settings.ai_opts);
+        let mut ai = (entry.build)(ai::seeded_rng(seed), &params);
+        if let Err(e) = replay::run(&args.arg_frames, &mut *ai, args.flag_diff.as_ref().map(|s| s.as_ref())) {
+            println!("Couldn't replay: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let url = Url::parse(format!("ws://{}:{}", game_host, port).as_ref()).unwrap();
+    if args.cmd_tournament {
+        let ais: Vec<_> = args.arg_ais.iter().map(|name| {
+            let opts = match config_file {
+                Some(ref config_file) => config_file.ai_params(name).unwrap_or_else(|e| fail(e.to_string())),
+                None => Vec::new()
+            };
+            resolve_ai(name, &opts)
+        }).collect();
+        let standings = tournament::run(&ais, args.flag_games, &rules::default_config(), seed);
+        tournament::print_standings(&standings);
+        return;
+    }
 
-    let request = Client::connect(url).unwrap();
+    let (ai_entry, ai_params) = resolve_ai(&ai_name, &settings.ai_opts);
+    let validation_mode = ValidationMode::from_name(&settings.validate).unwrap_or_else(|| {
+        println!("Unknown validation mode {}, use report or repair", settings.validate);
+        std::process::exit(1);
+    });
 
-    let response_option = request.send();
-    let response = match response_option {
-        Err(e) => {
-            println!("{:?}", e.cause().unwrap().description());
-            return;
-        }
-        Ok(response) => response
+    let keep_ai_state = settings.keep_ai_state;
+    let mut watchdog = Watchdog::new((ai_entry.build)(ai::seeded_rng(seed), &ai_params));
+    let mut recorder = settings.record.as_ref().map(|dir| Recorder::new(dir, &settings.team_name));
+    let options = SessionOptions {
+        team_name: settings.team_name.clone(),
+        validation_mode: validation_mode,
+        margin_ms: settings.margin
     };
-    response.validate().unwrap();
-
-    let (mut sender, mut receiver) = response.begin().split();
-
-    // when _receive_loop goes out of scope, the thread is waited on
-    let _receive_loop = thread::scoped(move || {
-        let mut ai = ai::from_name(ai_name);
-        for message in receiver.incoming_messages() {
-            let message = match message {
-                Ok(m) => m,
-                Err(e) => {
-                    println!("Receive loop error: {:?}", e);
-                    return;
+    let retries = settings.retries;
+    let backoff = Backoff::new(settings.retry_delay, 30000);
+    let single_game = settings.webgame.is_none();
+
+    let results = {
+        let mut play_at = |host: &str, port: u32| {
+            let url = format!("ws://{}:{}", host, port);
+            supervisor::supervise(retries, backoff.clone(), |attempt| {
+                if attempt > 1 && !keep_ai_state {
+                    watchdog.replace((ai_entry.build)(ai::seeded_rng(seed), &ai_params));
                 }
-            };
-            match message {
-                WSMessage::Close(_) => {
-                    println!("Closing");
-                    return;
-                }
-                WSMessage::Text(msg) => {
-                    let message = incoming::parse_message(msg);
-                    match message {
-                        Message::ConnectedMessage(_) => {
-                            let _ = sender.send_message(WSMessage::Text(json::to_string(& JoinMessage {
-                                type_: "join".to_string(),
-                                team_name: team_name.clone() // clone because compiler doesn't know that this is called just once
-                            }).unwrap()));
-                        },
-                        Message::EndMessage(msg) => {
-                            println!("Thanks for playing!");
-                            match msg.winner_team_id {
-                                Some(winner) => println!("Winner was {}, you are: {}", winner, msg.you.team_id),
-                                None => println!("There was no winner :(")
-                            };
-                            return;
-                        },
-                        Message::EventsMessage(msg) => {
-                            ai.set_state(msg.config, msg.you, msg.other_teams);
-                            let _ = sender.send_message(WSMessage::Text(json::to_string(&to_actionsmessage(
-                                msg.round_id, ai.respond(msg.events))).unwrap()));
-                        },
-                        _ => ()
+                let result = session::play(&url, &options, &mut watchdog, recorder.as_mut());
+                if result.is_err() {
+                    if let Some(ref mut recorder) = recorder {
+                        recorder.disconnected();
                     }
                 }
-                _ => println!("Got {:?}. Don't know what to do with it", message)
+                result
+            }).map_err(|e| e.to_string())
+        };
+
+        match settings.webgame {
+            None => vec![webgame::BatchResult {
+                opponents: Vec::new(),
+                game_id: None,
+                outcome: play_at(&settings.host, settings.port)
+            }],
+            Some(webgame) => {
+                if webgame.contains(':') {
+                    fail("--webgame only takes opponent names, give the credentials in TYCKITING_USER and \
+                          TYCKITING_PASSWORD or the config file".to_string());
+                }
+                let credentials = match (settings.user, settings.password) {
+                    (Some(user), Some(password)) => webgame::Credentials { user: user, password: password },
+                    _ => fail(webgame::WebGameError::MissingCredentials.to_string())
+                };
+                let api_url = settings.api_url.unwrap_or_else(|| webgame::default_api_url(&settings.host));
+                let opponents: Vec<String> = webgame.split(',').map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty()).collect();
+                if opponents.is_empty() {
+                    fail("--webgame needs at least one opponent name".to_string());
+                }
+                if settings.repeat == 0 {
+                    fail("--repeat needs to be at least 1".to_string());
+                }
+                let games = webgame::plan_batch(&opponents, settings.each, settings.repeat);
+                let game_count = games.len();
+
+                let mut results = Vec::new();
+                for (i, opponents) in games.into_iter().enumerate() {
+                    println!("Game {}/{} against {}", i + 1, game_count, opponents.connect(", "));
+                    let game = match webgame::request_game(&api_url, &credentials, &opponents) {
+                        Ok(game) => game,
+                        Err(e) => {
+                            if game_count == 1 {
+                                fail(format!("Couldn't start a web game: {}", e));
+                            }
+                            println!("Couldn't start a web game: {}", e);
+                            results.push(webgame::BatchResult { opponents: opponents, game_id: None, outcome: Err(e.to_string()) });
+                            continue;
+                        }
+                    };
+                    println!("You can spectate at http://{}:{}.", game.host, game.port);
+                    let outcome = play_at(&game.host, game.port);
+                    results.push(webgame::BatchResult { opponents: opponents, game_id: Some(game.game_id), outcome: outcome });
+                }
+                results
             }
         }
-    });
-}
-
-#[derive(Debug, Deserialize, Default)]
-pub struct GameConfig {
-    bots: i32,
-    #[serde(rename="fieldRadius")] field_radius: i32,
-    #[serde(rename="move")] move_: u32,
-    #[serde(rename="startHp")] start_hp: i32,
-    cannon: i32,
-    radar: i32,
-    see: i32,
-    #[serde(rename="maxCount")] max_count: i32,
-    #[serde(rename="loopTime")] loop_time: i32,
-    asteroids: Option<i32>
-}
-
-#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
-pub struct Position {
-    x: i32,
-    y: i32
-}
-
-impl Copy for Position {}
+    };
 
-impl Position {
-    #[allow(dead_code)]
-    fn distance(&self, other: Position) -> i32 {
-        // see: http://keekerdc.com/2011/03/hexagon-grids-coordinate-systems-and-distance-calculations/
-        // and http://www.redblobgames.com/grids/hexagons/
-        max((other.x - self.x).abs(),
-            max((other.y - self.y).abs(),
-            ((-other.x - other.y) - (-self.x - self.y)).abs()))
-    }
-    #[allow(dead_code)]
-    fn positions_within(&self, distance: u32) -> Vec<Position> {
-        // see http://www.redblobgames.com/grids/hexagons/
-        let dist = distance as i32;
-        let mut ret: Vec<Position> = Vec::new();
-        for dx in -dist..dist + 1 {
-            for dy in (max(-dist, -dx - dist)..min(dist, -dx + dist) + 1) {
-                ret.push(Position { x: self.x + dx, y: self.y + dy});
-            }
+    watchdog.report(&ai_name);
+    if single_game || results.len() == 1 {
+        if let Err(ref e) = results[0].outcome {
+            println!("Giving up after {} attempts, {}", retries, e);
+            std::process::exit(2);
         }
-        ret
+        return;
     }
-    #[allow(dead_code)]
-    fn positions_at(&self, distance: u32, field_radius: i32) -> Vec<Position> {
-        let dist = distance as i32;
-        let mut ret: Vec<Position> = Vec::new();
-        // start position is bottom left corner
-        let mut pos = Position { x: self.x - dist, y: self.y + dist };
-        //println!("Starting from {:?}, corner at distance {:?} is {:?}", self, dist, pos);
-        let directions = vec![
-            Position { x:  1, y:  0 },
-            Position { x:  1, y: -1 },
-            Position { x:  0, y: -1 },
-            Position { x: -1, y:  0 },
-            Position { x: -1, y:  1 },
-            Position { x:  0, y:  1 },
-        ];
-        // walk the circumference, each edge is always the length of
-        // the radius
-        for i in 0..6 {
-            for j in 0..dist {
-                let x = pos.x;
-                let y = pos.y;
-                if pos.distance(Position { x: 0, y: 0 }) < field_radius {
-                    ret.push(pos);
-                }
-                let ref dir = directions[i];
-                pos = Position { x: x + dir.x, y: y + dir.y }
-            }
-        }
-        ret
-    }
-    #[allow(dead_code)]
-    fn move_towards(&self, target: Position, steps: u32) -> Position {
-        let allowed_positions = self.positions_within(steps);
-        allowed_positions.iter().fold(*self, |memo, pos| {
-            if pos.distance(target) < memo.distance(target) {
-                *pos
-            }
-            else {
-                memo
-            }
-        })
+    webgame::print_summary(&results);
+    if results.iter().any(|result| result.outcome.is_err()) {
+        std::process::exit(2);
     }
-    fn move_away_from(&self, target: &Position, steps: u32) -> Position {
-        let allowed_positions = self.positions_within(steps);
-        allowed_positions.iter().fold(*self, |memo, pos| {
-            if pos.distance(*target) > memo.distance(*target) {
-                *pos
-            }
-            else {
-                memo
-            }
-        })
-    }
-    fn contains_any_within(&self, positions: Vec<Position>, steps: i32) -> bool {
-        let usteps = steps as u32;
-        let area = self.positions_within(usteps);
-        for pos in positions {
-            let sames: Vec<&Position> = area.iter()
-                .filter(|p| p.x == pos.x && p.y == pos.y)
-                .collect();
-            if ! sames.is_empty() {
-                return true
-            }
-        }
-        false
-    }
-}
-
-#[test]
-fn check_distance() {
-    assert!(Position { x: 0, y: 0}.distance(Position {x: 1, y: 0}) == 1);
-    assert!(Position { x: 0, y: 0}.distance(Position {x: 1, y: 3}) == 4);
-    assert!(Position { x: 0, y: 0}.distance(Position {x: 0, y: 5}) == 5);
-}
-
-#[test]
-fn check_positions_within() {
-    let pos = Position { x: 0, y: 0};
-    assert!( pos.positions_within(1).len() == 7);
-    assert!( pos.positions_within(2).len() == 19);
 }
 
-#[derive(Debug, Serialize)]
-struct JoinMessage {
-    #[serde(rename="type")] type_: String,
-    #[serde(rename="teamName")] team_name: String
-}
-
-#[derive(Debug, Serialize)]
-struct SerializableAction {
-    #[serde(rename="type")] type_: String,
-    #[serde(rename="botId")] bot_id: u32,
-    pos: Position
-}
-
-#[derive(Debug, Serialize)]
-struct ActionsMessage {
-    #[serde(rename="type")] type_: String,
-    #[serde(rename="roundId")] round_id: u32,
-    actions: Vec<SerializableAction>
-}
-
-#[allow(dead_code)]
-fn to_actionsmessage(round_id: u32, actions: Vec<Action>) -> ActionsMessage {
-    let serializable_actions = actions.into_iter().map(|action| {
-        match action {
-            Action::RadarAction(a) => SerializableAction {type_: "radar".to_string(), bot_id: a.bot_id, pos: a.pos},
-            Action::MoveAction(a) => SerializableAction {type_: "move".to_string(), bot_id: a.bot_id, pos: a.pos},
-            Action::CannonAction(a) => SerializableAction {type_: "cannon".to_string(), bot_id: a.bot_id, pos: a.pos}
-        }
-    }).collect();
-    ActionsMessage { type_: "actions".to_string(), round_id: round_id, actions: serializable_actions }
+fn fail(message: String) -> ! {
+    println!("{}", message);
+    std::process::exit(1);
 }
 
-#[test]
-fn test_actionsmessage_serialization() {
-    let generated = json::to_string(&to_actionsmessage(2u32, vec![Action::CannonAction(
-            ai::CannonAction {bot_id: 1u32, pos: Position { x: -1, y: 3 }})])).unwrap();
-    let wanted = "{\"type\":\"actions\",\"roundId\":2,\"actions\":[{\"type\":\"cannon\",\"botId\":1,\"pos\":{\"x\":-1,\"y\":3}}]}".to_string();
-    assert!( generated == wanted);
+/// Looks up the AI and applies `opts` over its default parameters, exiting
+/// with a message if either is wrong.
+fn resolve_ai(name: &str, opts: &[(String, String)]) -> (&'static ai::AiEntry, ai::Params) {
+    let entry = ai::find(name).unwrap_or_else(|e| fail(e.to_string()));
+    let params = entry.params(opts).unwrap_or_else(|e| fail(e.to_string()));
+    (entry, params)
 }
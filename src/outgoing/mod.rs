@@ -0,0 +1,239 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::Position;
+use super::ai::{Action, MoveAction, RadarAction, CannonAction};
+use super::incoming::ParseError;
+use serde::json::{self, Value};
+use std::error::Error;
+use std::fmt;
+
+/// Everything that can go wrong while reading a message meant for the server.
+#[derive(Debug)]
+pub enum CommandError {
+    /// Not JSON, or a field is missing or has the wrong type
+    Malformed(ParseError),
+    UnknownMessageType(String),
+    UnknownActionType(String)
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Malformed(ref e) => write!(f, "{}", e),
+            CommandError::UnknownMessageType(ref kind) => write!(f, "unknown message type `{}`", kind),
+            CommandError::UnknownActionType(ref kind) => write!(f, "unknown action type `{}`", kind)
+        }
+    }
+}
+
+impl Error for CommandError {
+    fn description(&self) -> &str {
+        match *self {
+            CommandError::Malformed(ref e) => e.description(),
+            CommandError::UnknownMessageType(_) => "unknown message type",
+            CommandError::UnknownActionType(_) => "unknown action type"
+        }
+    }
+}
+
+/// Lists every message and every action with its wire `type` tag.
+/// `OutgoingMessage::kind`, `action_kind` and the conversions of actions from
+/// and to the wire format are generated from this one table, so a new action
+/// only needs a line here and a new message a line and its fields.
+macro_rules! wire_tags {
+    (messages { $($message:ident => $message_tag:tt),* }
+     actions { $($variant:ident => $tag:tt),* }) => {
+        impl OutgoingMessage {
+            /// The wire `type` tag of the message.
+            pub fn kind(&self) -> &'static str {
+                match *self {
+                    $(OutgoingMessage::$message { .. } => $message_tag),*
+                }
+            }
+        }
+
+        /// The wire `type` tag of `action`.
+        pub fn action_kind(action: &Action) -> &'static str {
+            match *action {
+                $(Action::$variant(_) => $tag),*
+            }
+        }
+
+        fn to_wire(action: &Action) -> WireAction {
+            let (bot_id, pos) = match *action {
+                $(Action::$variant(ref a) => (a.bot_id, a.pos)),*
+            };
+            WireAction { type_: action_kind(action).to_string(), bot_id: bot_id, pos: pos }
+        }
+
+        fn from_wire(action: WireAction) -> Result<Action, CommandError> {
+            match action.type_.as_ref() {
+                $($tag => Ok(Action::$variant($variant { bot_id: action.bot_id, pos: action.pos })),)*
+                _ => Err(CommandError::UnknownActionType(action.type_.clone()))
+            }
+        }
+    }
+}
+
+wire_tags! {
+    messages {
+        Join => "join",
+        Actions => "actions"
+    }
+    actions {
+        CannonAction => "cannon",
+        MoveAction => "move",
+        RadarAction => "radar"
+    }
+}
+
+/// Everything the client can send to the server. The wire `type` tags come
+/// from the `wire_tags!` table above.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutgoingMessage {
+    Join { team_name: String },
+    Actions { round_id: u32, actions: Vec<Action> }
+}
+
+impl OutgoingMessage {
+    pub fn to_json(&self) -> String {
+        let kind = self.kind().to_string();
+        let serialized = match *self {
+            OutgoingMessage::Join { ref team_name } => json::to_string(&JoinMessage {
+                type_: kind,
+                team_name: team_name.clone()
+            }),
+            OutgoingMessage::Actions { round_id, ref actions } => json::to_string(&ActionsMessage {
+                type_: kind,
+                round_id: round_id,
+                actions: actions.iter().map(to_wire).collect()
+            })
+        };
+        serialized.unwrap()
+    }
+
+    /// Reads a message in the format the server expects, e.g. to check what
+    /// was sent or to play the server side.
+    pub fn parse(msg: &str) -> Result<OutgoingMessage, CommandError> {
+        let value: Value = try!(json::from_str(msg).map_err(|e| CommandError::Malformed(ParseError::InvalidJson(e))));
+        let kind = try!(tag(&value, "type"));
+        match kind.as_ref() {
+            "join" => {
+                let join: JoinMessage = try!(json::value::from_value(value).map_err(|_| malformed("teamName")));
+                Ok(OutgoingMessage::Join { team_name: join.team_name })
+            },
+            "actions" => {
+                let round_id = match value.find("roundId").map(|id| id.as_u64()) {
+                    Some(Some(id)) => id as u32,
+                    Some(None) => return Err(malformed("roundId")),
+                    None => return Err(CommandError::Malformed(ParseError::MissingField("roundId")))
+                };
+                let wire_actions = match value.find("actions").map(|actions| actions.as_array()) {
+                    Some(Some(actions)) => actions,
+                    Some(None) => return Err(malformed("actions")),
+                    None => return Err(CommandError::Malformed(ParseError::MissingField("actions")))
+                };
+                let mut actions = Vec::with_capacity(wire_actions.len());
+                for action in wire_actions.iter() {
+                    try!(tag(action, "type"));
+                    let wire: WireAction = try!(json::value::from_value(action.clone()).map_err(|_| malformed("actions")));
+                    actions.push(try!(from_wire(wire)));
+                }
+                Ok(OutgoingMessage::Actions { round_id: round_id, actions: actions })
+            },
+            _ => Err(CommandError::UnknownMessageType(kind.clone()))
+        }
+    }
+}
+
+fn malformed(field: &'static str) -> CommandError {
+    CommandError::Malformed(ParseError::WrongType(field))
+}
+
+fn tag(value: &Value, name: &'static str) -> Result<String, CommandError> {
+    match value.find(name) {
+        Some(&Value::String(ref kind)) => Ok(kind.clone()),
+        Some(_) => Err(malformed(name)),
+        None => Err(CommandError::Malformed(ParseError::MissingField(name)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JoinMessage {
+    #[serde(rename="type")] type_: String,
+    #[serde(rename="teamName")] team_name: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WireAction {
+    #[serde(rename="type")] type_: String,
+    #[serde(rename="botId")] bot_id: u32,
+    pos: Position
+}
+
+#[derive(Debug, Serialize)]
+struct ActionsMessage {
+    #[serde(rename="type")] type_: String,
+    #[serde(rename="roundId")] round_id: u32,
+    actions: Vec<WireAction>
+}
+
+#[test]
+fn test_actionsmessage_serialization() {
+    let generated = OutgoingMessage::Actions { round_id: 2u32, actions: vec![Action::CannonAction(
+            CannonAction {bot_id: 1u32, pos: Position { x: -1, y: 3 }})] }.to_json();
+    let wanted = "{\"type\":\"actions\",\"roundId\":2,\"actions\":[{\"type\":\"cannon\",\"botId\":1,\"pos\":{\"x\":-1,\"y\":3}}]}".to_string();
+    assert!( generated == wanted);
+}
+
+#[test]
+fn test_joinmessage_round_trip() {
+    let wire = "{\"type\":\"join\",\"teamName\":\"Ferrous Bueller\"}";
+    let message = OutgoingMessage::Join { team_name: "Ferrous Bueller".to_string() };
+    assert!(message.to_json() == wire);
+    assert!(OutgoingMessage::parse(wire).unwrap() == message);
+}
+
+#[test]
+fn test_actionsmessage_round_trip() {
+    let wire = concat!("{\"type\":\"actions\",\"roundId\":7,\"actions\":[",
+        "{\"type\":\"move\",\"botId\":0,\"pos\":{\"x\":1,\"y\":-2}},",
+        "{\"type\":\"radar\",\"botId\":1,\"pos\":{\"x\":4,\"y\":0}},",
+        "{\"type\":\"cannon\",\"botId\":2,\"pos\":{\"x\":-3,\"y\":3}}]}");
+    let message = OutgoingMessage::parse(wire).unwrap();
+    assert!(message == OutgoingMessage::Actions { round_id: 7, actions: vec![
+        Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 1, y: -2 } }),
+        Action::RadarAction(RadarAction { bot_id: 1, pos: Position { x: 4, y: 0 } }),
+        Action::CannonAction(CannonAction { bot_id: 2, pos: Position { x: -3, y: 3 } })
+    ]});
+    assert!(message.to_json() == wire);
+}
+
+#[test]
+fn test_parse_unknown_action() {
+    let wire = "{\"type\":\"actions\",\"roundId\":7,\"actions\":[{\"type\":\"warp\",\"botId\":0,\"pos\":{\"x\":1,\"y\":-2}}]}";
+    match OutgoingMessage::parse(wire) {
+        Err(CommandError::UnknownActionType(ref kind)) => assert!(kind == "warp"),
+        other => panic!("Expected unknown action type, got {:?}", other)
+    }
+    match OutgoingMessage::parse("{\"type\":\"surrender\"}") {
+        Err(CommandError::UnknownMessageType(ref kind)) => assert!(kind == "surrender"),
+        other => panic!("Expected unknown message type, got {:?}", other)
+    }
+}
@@ -0,0 +1,180 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+use super::Position;
+use super::board::Board;
+use super::hexset::{HexSet, HexMap};
+
+/// How many single hex steps it takes to get from `start` to every hex
+/// within `max_steps`, going around `blocked` hexes and staying on the board.
+pub fn distances(board: &Board, blocked: &HexSet, start: Position, max_steps: u32) -> HexMap<u32> {
+    let mut distances = HexMap::for_board(board);
+    if !board.contains(start) {
+        return distances;
+    }
+    let _ = distances.insert(start, 0);
+    let mut queue = VecDeque::new();
+    queue.push_back((start, 0));
+    while let Some((pos, steps)) = queue.pop_front() {
+        if steps == max_steps {
+            continue;
+        }
+        for next in pos.neighbors().into_iter() {
+            if board.contains(next) && !blocked.contains(next) && !distances.contains_key(next) {
+                let _ = distances.insert(next, steps + 1);
+                queue.push_back((next, steps + 1));
+            }
+        }
+    }
+    distances
+}
+
+/// The hexes a bot at `start` can get to in at most `steps` steps, `start`
+/// included.
+pub fn reachable(board: &Board, blocked: &HexSet, start: Position, steps: u32) -> HexSet {
+    distances(board, blocked, start, steps).keys()
+}
+
+#[derive(PartialEq, Eq)]
+struct Node {
+    estimate: i32,
+    steps: i32,
+    pos: (i32, i32)
+}
+
+impl Ord for Node {
+    // reversed so that BinaryHeap pops the lowest estimate first, ties go to
+    // the node furthest along
+    fn cmp(&self, other: &Node) -> Ordering {
+        (other.estimate, self.steps, other.pos).cmp(&(self.estimate, other.steps, self.pos))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A shortest path from `start` to `goal`, both included, found with A*.
+/// `None` if the goal is blocked, off the board or walled off.
+pub fn shortest_path(board: &Board, blocked: &HexSet, start: Position, goal: Position) -> Option<Vec<Position>> {
+    if !board.contains(start) || !board.contains(goal) || blocked.contains(goal) {
+        return None;
+    }
+    let mut came_from: HexMap<Position> = HexMap::for_board(board);
+    let mut best: HexMap<i32> = HexMap::for_board(board);
+    let mut open = BinaryHeap::new();
+    let _ = best.insert(start, 0);
+    open.push(Node { estimate: start.distance(goal), steps: 0, pos: (start.x, start.y) });
+
+    while let Some(Node { steps, pos: (x, y), .. }) = open.pop() {
+        let pos = Position { x: x, y: y };
+        if pos == goal {
+            let mut path = vec![goal];
+            let mut current = goal;
+            while let Some(&previous) = came_from.get(current) {
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if best.get(pos).map(|&b| steps > b).unwrap_or(false) {
+            continue;
+        }
+        for next in pos.neighbors().into_iter() {
+            if !board.contains(next) || blocked.contains(next) {
+                continue;
+            }
+            if best.get(next).map(|&b| steps + 1 >= b).unwrap_or(false) {
+                continue;
+            }
+            let _ = best.insert(next, steps + 1);
+            let _ = came_from.insert(next, pos);
+            open.push(Node { estimate: steps + 1 + next.distance(goal), steps: steps + 1, pos: (next.x, next.y) });
+        }
+    }
+    None
+}
+
+/// Where a bot at `from` should go with `steps` steps to get closest to
+/// `target` without crossing `blocked` hexes.
+pub fn move_towards(board: &Board, blocked: &HexSet, from: Position, target: Position, steps: u32) -> Position {
+    if let Some(path) = shortest_path(board, blocked, from, target) {
+        return path[::std::cmp::min(steps as usize, path.len() - 1)];
+    }
+    reachable(board, blocked, from, steps).positions().into_iter()
+        .fold(from, |memo, pos| if pos.distance(target) < memo.distance(target) { pos } else { memo })
+}
+
+/// Where a bot at `from` should go with `steps` steps to get furthest from
+/// `threat` without crossing `blocked` hexes.
+pub fn move_away_from(board: &Board, blocked: &HexSet, from: Position, threat: Position, steps: u32) -> Position {
+    reachable(board, blocked, from, steps).positions().into_iter()
+        .fold(from, |memo, pos| if pos.distance(threat) > memo.distance(threat) { pos } else { memo })
+}
+
+#[cfg(test)]
+fn walled_board() -> (Board, HexSet) {
+    // a wall along x == 0 with a gap at the south end
+    let board = Board::with_radius(5);
+    let wall = HexSet::from_positions(5, (-5..5).map(|y| Position { x: 0, y: y }));
+    (board, wall)
+}
+
+#[test]
+fn test_reachable() {
+    let (board, wall) = walled_board();
+    let start = Position { x: -1, y: 0 };
+    let open = reachable(&board, &HexSet::for_board(&board), start, 2);
+    assert!(open.len() == 19);
+    let walled = reachable(&board, &wall, start, 2);
+    assert!(walled.contains(start));
+    assert!(walled.positions().iter().all(|pos| pos.x < 0 && !wall.contains(*pos)));
+    assert!(walled.len() == open.len() - open.intersection(&wall).len() - 3);
+    // at the edge of the board
+    assert!(reachable(&board, &wall, Position { x: -5, y: 0 }, 1).len() == 4);
+}
+
+#[test]
+fn test_shortest_path() {
+    let (board, wall) = walled_board();
+    let start = Position { x: -2, y: -1 };
+    let goal = Position { x: 2, y: -1 };
+    let path = shortest_path(&board, &wall, start, goal).unwrap();
+    assert!(path[0] == start && path[path.len() - 1] == goal);
+    assert!(path.windows(2).all(|pair| pair[0].distance(pair[1]) == 1));
+    assert!(path.iter().all(|pos| board.contains(*pos) && !wall.contains(*pos)));
+    assert!(path.len() as u32 - 1 == *distances(&board, &wall, start, 100).get(goal).unwrap());
+    assert!(path.len() as i32 - 1 > start.distance(goal));
+
+    let no_walls = HexSet::for_board(&board);
+    assert!(shortest_path(&board, &no_walls, start, goal).unwrap().len() as i32 == start.distance(goal) + 1);
+    assert!(shortest_path(&board, &wall, start, Position { x: 0, y: 0 }).is_none());
+    assert!(shortest_path(&board, &wall, start, Position { x: 9, y: 0 }).is_none());
+
+    let closed = HexSet::from_positions(5, (-5..6).map(|y| Position { x: 0, y: y }));
+    assert!(shortest_path(&board, &closed, start, goal).is_none());
+    // with the goal out of reach the bot still gets as close as it can
+    let pos = move_towards(&board, &closed, start, goal, 2);
+    assert!(pos.x == -1 && start.distance(pos) <= 2);
+}
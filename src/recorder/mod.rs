@@ -0,0 +1,186 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate time;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use serde::json;
+
+/// How many frames are held back at most while waiting for a game to start.
+/// Only a handful come before the start message, more means it never came.
+const MAX_PENDING: usize = 100;
+
+/// Writes every frame of a game, in and out, to a JSONL file in `dir`.
+/// Each line looks like
+/// `{"time":1445157557123,"direction":"in","roundId":3,"frame":{...}}`
+/// where `time` is in milliseconds since the epoch and `roundId` is null for
+/// frames that don't belong to a round. Frames seen before the game starts
+/// are held back until the file is opened, since its name needs the opponents,
+/// up to `MAX_PENDING` of them.
+pub struct Recorder {
+    dir: PathBuf,
+    team_name: String,
+    pending: Vec<String>,
+    // frames not held back because `pending` was full
+    dropped: u32,
+    file: Option<File>
+}
+
+fn now_ms() -> u64 {
+    let now = time::get_time();
+    now.sec as u64 * 1000 + now.nsec as u64 / 1000000
+}
+
+fn file_name_part(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() { c } else { '-' }).collect()
+}
+
+impl Recorder {
+    pub fn new(dir: &str, team_name: &str) -> Recorder {
+        Recorder {
+            dir: PathBuf::from(dir),
+            team_name: team_name.to_string(),
+            pending: Vec::new(),
+            dropped: 0,
+            file: None
+        }
+    }
+
+    /// Opens the file for a new game, named after the date, our team and the opponents.
+    pub fn start_game(&mut self, opponents: &[String]) -> io::Result<()> {
+        try!(fs::create_dir_all(&self.dir));
+        let date = time::strftime("%Y%m%d-%H%M%S", &time::now_utc()).unwrap();
+        let opponents: Vec<String> = opponents.iter().map(|name| file_name_part(name)).collect();
+        let path = self.dir.join(format!("{}_{}_vs_{}.jsonl", date, file_name_part(&self.team_name), opponents.connect("_")));
+        let mut file = try!(File::create(&path));
+        for line in self.pending.iter() {
+            try!(file.write_all(line.as_bytes()));
+        }
+        self.pending.clear();
+        self.dropped = 0;
+        println!("Recording game to {}", path.display());
+        self.file = Some(file);
+        Ok(())
+    }
+
+    pub fn end_game(&mut self) {
+        self.file = None;
+    }
+
+    /// Closes the game the connection dropped in and forgets the frames held
+    /// back for a game that never started, so the next connection starts afresh.
+    pub fn disconnected(&mut self) {
+        self.end_game();
+        self.pending.clear();
+        self.dropped = 0;
+    }
+
+    /// Records a frame from the server. `is_json` is false for frames that
+    /// couldn't be parsed, those are stored as a JSON string instead.
+    pub fn inbound(&mut self, frame: &str, round_id: Option<u32>, is_json: bool) {
+        self.record("in", frame, round_id, is_json);
+    }
+
+    pub fn outbound(&mut self, frame: &str, round_id: Option<u32>) {
+        self.record("out", frame, round_id, true);
+    }
+
+    fn record(&mut self, direction: &str, frame: &str, round_id: Option<u32>, is_json: bool) {
+        let frame = if is_json { frame.trim().to_string() } else { json::to_string(&frame.to_string()).unwrap() };
+        let round_id = match round_id {
+            Some(id) => id.to_string(),
+            None => "null".to_string()
+        };
+        let line = format!("{{\"time\":{},\"direction\":\"{}\",\"roundId\":{},\"frame\":{}}}\n",
+                           now_ms(), direction, round_id, frame);
+        let failed = match self.file {
+            Some(ref mut file) => file.write_all(line.as_bytes()).is_err(),
+            None => {
+                if self.pending.len() < MAX_PENDING {
+                    self.pending.push(line);
+                } else {
+                    if self.dropped == 0 {
+                        println!("No game started after {} frames, not holding back any more of them", MAX_PENDING);
+                    }
+                    self.dropped += 1;
+                }
+                false
+            }
+        };
+        if failed {
+            println!("Couldn't write to the recording, stopping it");
+            self.file = None;
+        }
+    }
+}
+
+#[test]
+fn test_recorder_writes_one_line_per_frame() {
+    use std::io::Read;
+    let dir = ::std::env::temp_dir().join(format!("tyckiting-recorder-{}", now_ms()));
+    let mut recorder = Recorder::new(dir.to_str().unwrap(), "Ferrous Bueller");
+    recorder.inbound(include_str!("../incoming/fixtures/connected.json"), None, true);
+    recorder.start_game(&["Rooney".to_string()]).unwrap();
+    recorder.inbound("not json", Some(1), false);
+    recorder.outbound("{\"type\":\"actions\",\"roundId\":1,\"actions\":[]}", Some(1));
+    recorder.end_game();
+
+    let paths: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert!(paths.len() == 1);
+    let path = &paths[0];
+    assert!(path.file_name().unwrap().to_str().unwrap().ends_with("_Ferrous-Bueller_vs_Rooney.jsonl"));
+    let mut contents = String::new();
+    File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+    let lines: Vec<json::Value> = contents.lines().map(|line| json::from_str(line).unwrap()).collect();
+    assert!(lines.len() == 3);
+    assert!(lines[0].find("direction").unwrap().as_string() == Some("in"));
+    assert!(lines[0].find("roundId").unwrap().is_null());
+    assert!(lines[0].find("frame").unwrap().find("teamId").unwrap().as_u64() == Some(1));
+    assert!(lines[1].find("frame").unwrap().as_string() == Some("not json"));
+    assert!(lines[2].find("direction").unwrap().as_string() == Some("out"));
+    assert!(lines[2].find("roundId").unwrap().as_u64() == Some(1));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_recorder_caps_frames_before_the_start() {
+    let dir = ::std::env::temp_dir().join(format!("tyckiting-recorder-cap-{}", now_ms()));
+    let mut recorder = Recorder::new(dir.to_str().unwrap(), "Ferrous Bueller");
+    for _ in 0..MAX_PENDING * 3 {
+        recorder.inbound("{\"type\":\"bogus\"}", None, true);
+    }
+    assert!(recorder.pending.len() == MAX_PENDING && recorder.dropped == MAX_PENDING as u32 * 2);
+    recorder.disconnected();
+    assert!(recorder.pending.is_empty());
+}
+
+#[test]
+fn test_recorder_starts_afresh_after_a_disconnect() {
+    let dir = ::std::env::temp_dir().join(format!("tyckiting-recorder-disconnect-{}", now_ms()));
+    let mut recorder = Recorder::new(dir.to_str().unwrap(), "Ferrous Bueller");
+    recorder.start_game(&["Rooney".to_string()]).unwrap();
+    recorder.inbound("{\"type\":\"events\"}", Some(1), true);
+    recorder.disconnected();
+    assert!(recorder.file.is_none());
+    recorder.inbound(include_str!("../incoming/fixtures/connected.json"), None, true);
+    assert!(recorder.pending.len() == 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
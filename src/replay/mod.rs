@@ -0,0 +1,161 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+
+use serde::json::{self, Value};
+
+use super::incoming::{self, Message};
+use super::outgoing::OutgoingMessage;
+use super::ai::{self, Ai, Action};
+
+/// Recordings made with `--record` wrap each frame, raw websocket captures
+/// don't. Returns the frame of `line` if it went in `direction`, or the line
+/// itself if it isn't a recording line.
+fn unwrap_frame(line: &str, direction: &str) -> Option<String> {
+    let value: Value = match json::from_str(line) {
+        Ok(value) => value,
+        Err(_) => return Some(line.to_string())
+    };
+    match (value.find("direction").and_then(|d| d.as_string()), value.find("frame")) {
+        (Some(d), Some(frame)) => {
+            if d == direction { Some(json::to_string(frame).unwrap()) } else { None }
+        },
+        _ => Some(line.to_string())
+    }
+}
+
+/// The server frames in `contents`, one per non-empty line.
+pub fn read_frames(contents: &str) -> Vec<String> {
+    contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| unwrap_frame(line, "in"))
+        .collect()
+}
+
+/// The actions we sent in `contents`, by round id.
+pub fn read_sent_actions(contents: &str) -> BTreeMap<u32, Vec<Action>> {
+    let mut sent = BTreeMap::new();
+    for frame in contents.lines().filter(|line| !line.trim().is_empty()).filter_map(|line| unwrap_frame(line, "out")) {
+        match OutgoingMessage::parse(&frame) {
+            Ok(OutgoingMessage::Actions { round_id, actions }) => { sent.insert(round_id, actions); },
+            Ok(_) => (),
+            Err(e) => println!("Skipping unreadable line ({}): {}", e, frame)
+        }
+    }
+    sent
+}
+
+/// Feeds the frames to `ai` as a live game would and returns its actions per round.
+pub fn replay(frames: &[String], ai: &mut Ai) -> Vec<(u32, Vec<Action>)> {
+    let mut rounds = Vec::new();
+    for frame in frames.iter() {
+        match incoming::parse_message(frame) {
+            Ok(Message::ConnectedMessage(msg)) => ai.on_connected(msg.team_id, msg.config),
+            Ok(Message::StartMessage(msg)) => ai.on_game_start(msg),
+            Ok(Message::EventsMessage(msg)) => {
+                ai.on_round(msg.round_id);
+                ai.set_state(msg.config, msg.you, msg.other_teams);
+                let actions = ai.respond(msg.events);
+                ai.on_actions_sent(msg.round_id, &actions);
+                rounds.push((msg.round_id, actions));
+            },
+            Ok(Message::EndMessage(msg)) => ai.on_game_end(msg),
+            Ok(Message::Unknown { kind, .. }) => println!("Skipping unknown message type {}", kind),
+            Err(e) => println!("Skipping unreadable frame ({}): {}", e, frame)
+        }
+    }
+    rounds
+}
+
+fn same_actions(a: &[Action], b: &[Action]) -> bool {
+    a.len() == b.len() && a.iter().all(|action| b.contains(action))
+}
+
+/// Rounds where the replayed actions differ from what was sent, regardless
+/// of the order of the actions within a round.
+pub fn diff(replayed: &[(u32, Vec<Action>)], sent: &BTreeMap<u32, Vec<Action>>) -> Vec<u32> {
+    replayed.iter().filter(|&&(round_id, ref actions)| {
+        match sent.get(&round_id) {
+            Some(sent_actions) => !same_actions(actions, sent_actions),
+            None => true
+        }
+    }).map(|&(round_id, _)| round_id).collect()
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let mut contents = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut contents));
+    Ok(contents)
+}
+
+/// The `replay` subcommand: prints what `ai` would have done in the recorded
+/// game, and how that differs from `sent_path` if given.
+pub fn run(frames_path: &str, ai: &mut Ai, sent_path: Option<&str>) -> io::Result<()> {
+    let frames = read_frames(&try!(read_file(frames_path)));
+    let rounds = replay(&frames, ai);
+    for &(round_id, ref actions) in rounds.iter() {
+        println!("{}", OutgoingMessage::Actions { round_id: round_id, actions: actions.clone() }.to_json());
+    }
+    if let Some(sent_path) = sent_path {
+        let sent = read_sent_actions(&try!(read_file(sent_path)));
+        let differing = diff(&rounds, &sent);
+        for round_id in differing.iter() {
+            println!("Round {} differs", round_id);
+            println!("  replayed: {:?}", rounds.iter().find(|&&(id, _)| id == *round_id).map(|&(_, ref a)| a));
+            println!("  sent:     {:?}", sent.get(round_id));
+        }
+        println!("{}/{} rounds match", rounds.len() - differing.len(), rounds.len());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_frames_from_recording_and_raw() {
+    let recording = concat!(
+        "{\"time\":1,\"direction\":\"in\",\"roundId\":null,\"frame\":{\"type\":\"start\"}}\n",
+        "{\"time\":2,\"direction\":\"out\",\"roundId\":1,\"frame\":{\"type\":\"actions\",\"roundId\":1,\"actions\":[]}}\n",
+        "\n");
+    let frames = read_frames(recording);
+    assert!(frames == vec!["{\"type\":\"start\"}".to_string()]);
+    let raw = read_frames(include_str!("../incoming/fixtures/events.json"));
+    assert!(raw.len() == 1);
+    let sent = read_sent_actions(recording);
+    assert!(sent.get(&1) == Some(&vec![]));
+}
+
+#[test]
+fn test_replay_and_diff() {
+    use super::ai::{MoveAction, CannonAction};
+    use super::Position;
+    let frames = read_frames(concat!(include_str!("../incoming/fixtures/start.json"),
+                                     include_str!("../incoming/fixtures/events.json")));
+    let mut ai = ai::from_name("random", ai::seeded_rng(1)).unwrap();
+    let rounds = replay(&frames, &mut *ai);
+    assert!(rounds.len() == 1 && rounds[0].0 == 42);
+
+    let move_ = Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 1, y: 0 } });
+    let cannon = Action::CannonAction(CannonAction { bot_id: 2, pos: Position { x: 3, y: 0 } });
+    let replayed = vec![(1, vec![move_.clone(), cannon.clone()]), (2, vec![move_.clone()]), (3, vec![])];
+    let mut sent = BTreeMap::new();
+    sent.insert(1, vec![cannon.clone(), move_.clone()]);
+    sent.insert(2, vec![cannon.clone()]);
+    assert!(diff(&replayed, &sent) == vec![2, 3]);
+}
@@ -0,0 +1,384 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate rand;
+
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use self::rand::Rng;
+
+use super::{Position, GameConfig};
+use super::ai::Action;
+use super::board::Board;
+use super::incoming::{Bot, BotNoPosNoHp, Team, TeamNoPosNoHp, Event, DamagedEvent, HitEvent, DieEvent,
+                      SeeEvent, RadarEchoEvent, DetectedEvent, NoActionEvent, MoveEvent, SeeAsteroidEvent,
+                      ConnectedMessage, StartMessage, EventsMessage, EndMessage};
+
+/// Damage at the hex a cannon is aimed at
+const DIRECT_HIT_DAMAGE: i32 = 2;
+/// Damage at the other hexes within `config.cannon` of it
+const BLAST_DAMAGE: i32 = 1;
+
+/// The settings the Tyckiting server uses unless told otherwise.
+pub fn default_config() -> GameConfig {
+    GameConfig {
+        bots: 3,
+        field_radius: 14,
+        move_: 2,
+        start_hp: 10,
+        cannon: 1,
+        radar: 3,
+        see: 2,
+        max_count: 200,
+        loop_time: 300,
+        asteroids: Some(20)
+    }
+}
+
+/// The state of one game and the rules that advance it a round at a time.
+///
+/// A round is resolved in this order: moves, cannons, radars and finally what
+/// every bot can see from where it ended up. Actions for dead bots or bots of
+/// another team, second actions for a bot, moves longer than `config.move_`
+/// and targets outside the field are ignored, as are moves onto asteroids.
+pub struct Game {
+    pub config: GameConfig,
+    pub round_id: u32,
+    teams: Vec<Team>,
+    asteroids: Vec<Position>,
+    board: Board
+}
+
+impl Game {
+    /// Places the asteroids and `config.bots` bots per team on random free hexes.
+    pub fn new<R: Rng>(config: GameConfig, team_names: &[String], rng: &mut R) -> Game {
+        let mut free = Board::new(&config).hexes().to_vec();
+        rng.shuffle(&mut free);
+        let bot_count = config.bots as usize * team_names.len();
+        let asteroid_count = min(config.asteroids.unwrap_or(0) as usize, free.len().saturating_sub(bot_count));
+        let asteroids = free[..asteroid_count].to_vec();
+        let mut spots = free[asteroid_count..].iter();
+        let mut next_bot_id = 0;
+        let teams = team_names.iter().enumerate().map(|(i, name)| {
+            let team_id = i as u32 + 1;
+            let bots = (0..config.bots).map(|_| {
+                let bot_id = next_bot_id;
+                next_bot_id += 1;
+                Bot {
+                    bot_id: bot_id,
+                    name: format!("Bot {}", bot_id),
+                    team_id: team_id,
+                    hp: config.start_hp,
+                    alive: true,
+                    pos: *spots.next().expect("field too small for all bots")
+                }
+            }).collect();
+            Team { name: name.clone(), team_id: team_id, bots: bots }
+        }).collect();
+        Game::from_parts(config, teams, asteroids)
+    }
+
+    pub fn from_parts(config: GameConfig, teams: Vec<Team>, asteroids: Vec<Position>) -> Game {
+        let board = Board::new(&config);
+        Game { config: config, round_id: 0, teams: teams, asteroids: asteroids, board: board }
+    }
+
+    pub fn team_ids(&self) -> Vec<u32> {
+        self.teams.iter().map(|team| team.team_id).collect()
+    }
+
+    pub fn team(&self, team_id: u32) -> &Team {
+        self.teams.iter().find(|team| team.team_id == team_id).expect("no such team")
+    }
+
+    fn other_teams(&self, team_id: u32) -> Vec<TeamNoPosNoHp> {
+        self.teams.iter().filter(|team| team.team_id != team_id).map(|team| TeamNoPosNoHp {
+            name: team.name.clone(),
+            team_id: team.team_id,
+            bots: team.bots.iter().map(|bot| BotNoPosNoHp {
+                bot_id: bot.bot_id,
+                name: bot.name.clone(),
+                team_id: bot.team_id,
+                hp: None,
+                alive: bot.alive,
+                pos: None
+            }).collect()
+        }).collect()
+    }
+
+    pub fn connected_message(&self, team_id: u32) -> ConnectedMessage {
+        ConnectedMessage { team_id: team_id, config: self.config.clone() }
+    }
+
+    pub fn start_message(&self, team_id: u32) -> StartMessage {
+        StartMessage { config: self.config.clone(), you: self.team(team_id).clone(), other_teams: self.other_teams(team_id) }
+    }
+
+    pub fn events_message(&self, team_id: u32, events: Vec<Event>) -> EventsMessage {
+        EventsMessage {
+            round_id: self.round_id,
+            config: self.config.clone(),
+            you: self.team(team_id).clone(),
+            other_teams: self.other_teams(team_id),
+            events: events
+        }
+    }
+
+    pub fn end_message(&self, team_id: u32) -> EndMessage {
+        EndMessage { winner_team_id: self.winner(), you: self.team(team_id).clone() }
+    }
+
+    fn alive_team_ids(&self) -> Vec<u32> {
+        self.teams.iter().filter(|team| team.bots.iter().any(|bot| bot.alive)).map(|team| team.team_id).collect()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.alive_team_ids().len() <= 1 || self.round_id as i32 >= self.config.max_count
+    }
+
+    /// The last team standing, if there is exactly one.
+    pub fn winner(&self) -> Option<u32> {
+        let alive = self.alive_team_ids();
+        if alive.len() == 1 { Some(alive[0]) } else { None }
+    }
+
+    pub fn surviving_hp(&self, team_id: u32) -> i32 {
+        self.team(team_id).bots.iter().filter(|bot| bot.alive).fold(0, |hp, bot| hp + bot.hp)
+    }
+
+    fn bot_mut(&mut self, bot_id: u32) -> &mut Bot {
+        self.teams.iter_mut().flat_map(|team| team.bots.iter_mut()).find(|bot| bot.bot_id == bot_id).expect("no such bot")
+    }
+
+    fn empty_events(&self) -> BTreeMap<u32, Vec<Event>> {
+        self.team_ids().into_iter().map(|team_id| (team_id, Vec::new())).collect()
+    }
+
+    /// What each team sees before anyone has acted.
+    pub fn first_events(&self) -> BTreeMap<u32, Vec<Event>> {
+        let mut events = self.empty_events();
+        self.observe(&mut events, &mut BTreeSet::new());
+        events
+    }
+
+    /// Applies one round of actions, keyed by team id, and returns the events
+    /// each team gets to see about it.
+    pub fn play_round(&mut self, actions: &BTreeMap<u32, Vec<Action>>) -> BTreeMap<u32, Vec<Event>> {
+        let mut events = self.empty_events();
+        let mut moves = Vec::new();
+        let mut cannons = Vec::new();
+        let mut radars = Vec::new();
+
+        for team in self.teams.iter() {
+            let mut acted = HashSet::new();
+            let team_actions = actions.get(&team.team_id).map(|a| &a[..]).unwrap_or(&[]);
+            for action in team_actions.iter() {
+                let (bot_id, pos) = match *action {
+                    Action::CannonAction(ref a) => (a.bot_id, a.pos),
+                    Action::MoveAction(ref a) => (a.bot_id, a.pos),
+                    Action::RadarAction(ref a) => (a.bot_id, a.pos)
+                };
+                let bot = match team.bots.iter().find(|bot| bot.bot_id == bot_id && bot.alive) {
+                    Some(bot) => bot,
+                    None => continue
+                };
+                if !self.board.contains(pos) || acted.contains(&bot_id) {
+                    continue;
+                }
+                match *action {
+                    Action::MoveAction(_) => {
+                        if bot.pos.distance(pos) > self.config.move_ as i32 {
+                            continue;
+                        }
+                        moves.push((team.team_id, bot_id, pos));
+                    },
+                    Action::CannonAction(_) => cannons.push((team.team_id, bot_id, pos)),
+                    Action::RadarAction(_) => radars.push((team.team_id, bot_id, pos))
+                }
+                acted.insert(bot_id);
+            }
+            for bot in team.bots.iter().filter(|bot| bot.alive && !acted.contains(&bot.bot_id)) {
+                events.get_mut(&team.team_id).unwrap().push(Event::NoActionEvent(NoActionEvent { bot_id: bot.bot_id }));
+            }
+        }
+
+        for &(team_id, bot_id, pos) in moves.iter() {
+            if self.asteroids.contains(&pos) {
+                continue;
+            }
+            self.bot_mut(bot_id).pos = pos;
+            events.get_mut(&team_id).unwrap().push(Event::MoveEvent(MoveEvent { bot_id: bot_id, pos: pos }));
+        }
+
+        let mut damage: BTreeMap<u32, i32> = BTreeMap::new();
+        for &(team_id, shooter, target) in cannons.iter() {
+            for bot in self.teams.iter().flat_map(|team| team.bots.iter()).filter(|bot| bot.alive) {
+                let distance = bot.pos.distance(target);
+                if distance > self.config.cannon {
+                    continue;
+                }
+                *damage.entry(bot.bot_id).or_insert(0) += if distance == 0 { DIRECT_HIT_DAMAGE } else { BLAST_DAMAGE };
+                events.get_mut(&team_id).unwrap().push(Event::HitEvent(HitEvent { bot_id: bot.bot_id, source: shooter }));
+            }
+        }
+        let mut died = Vec::new();
+        for (&bot_id, &amount) in damage.iter() {
+            let bot = self.bot_mut(bot_id);
+            bot.hp -= amount;
+            if bot.hp <= 0 {
+                bot.hp = 0;
+                bot.alive = false;
+                died.push(bot_id);
+            }
+            let team_id = bot.team_id;
+            events.get_mut(&team_id).unwrap().push(Event::DamagedEvent(DamagedEvent { bot_id: bot_id, damage: amount as u32 }));
+        }
+        for &bot_id in died.iter() {
+            for team_events in events.values_mut() {
+                team_events.push(Event::DieEvent(DieEvent { bot_id: bot_id }));
+            }
+        }
+
+        let mut detected = BTreeSet::new();
+        for &(team_id, _, center) in radars.iter() {
+            for bot in self.teams.iter().filter(|team| team.team_id != team_id).flat_map(|team| team.bots.iter()) {
+                if bot.alive && bot.pos.distance(center) <= self.config.radar {
+                    events.get_mut(&team_id).unwrap().push(Event::RadarEchoEvent(RadarEchoEvent { pos: bot.pos }));
+                    detected.insert((bot.team_id, bot.bot_id));
+                }
+            }
+        }
+        self.observe(&mut events, &mut detected);
+
+        self.round_id += 1;
+        events
+    }
+
+    /// Adds what the living bots can see to `events` and tells the bots that
+    /// were seen or are in `detected` about it.
+    fn observe(&self, events: &mut BTreeMap<u32, Vec<Event>>, detected: &mut BTreeSet<(u32, u32)>) {
+        for team in self.teams.iter() {
+            let mut asteroids_seen = BTreeSet::new();
+            let team_events = events.get_mut(&team.team_id).unwrap();
+            for own in team.bots.iter().filter(|bot| bot.alive) {
+                for other in self.teams.iter().filter(|other| other.team_id != team.team_id).flat_map(|other| other.bots.iter()) {
+                    if other.alive && own.pos.distance(other.pos) <= self.config.see {
+                        team_events.push(Event::SeeEvent(SeeEvent { bot_id: other.bot_id, source: own.bot_id, pos: other.pos }));
+                        detected.insert((other.team_id, other.bot_id));
+                    }
+                }
+                for (i, asteroid) in self.asteroids.iter().enumerate() {
+                    if own.pos.distance(*asteroid) <= self.config.see && asteroids_seen.insert(i) {
+                        team_events.push(Event::SeeAsteroidEvent(SeeAsteroidEvent { pos: *asteroid }));
+                    }
+                }
+            }
+        }
+        for &(team_id, bot_id) in detected.iter() {
+            events.get_mut(&team_id).unwrap().push(Event::DetectedEvent(DetectedEvent { bot_id: bot_id }));
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_game() -> Game {
+    let bot = |bot_id: u32, team_id: u32, x: i32, y: i32| Bot {
+        bot_id: bot_id, name: format!("Bot {}", bot_id), team_id: team_id, hp: 3, alive: true, pos: Position { x: x, y: y }
+    };
+    let config = GameConfig { bots: 2, field_radius: 6, see: 1, ..default_config() };
+    Game::from_parts(config, vec![
+        Team { name: "Ferrous Bueller".to_string(), team_id: 1, bots: vec![bot(0, 1, -4, 0), bot(1, 1, -4, 2)] },
+        Team { name: "Rooney".to_string(), team_id: 2, bots: vec![bot(2, 2, 3, 0), bot(3, 2, 4, 0)] }
+    ], vec![Position { x: -2, y: 0 }])
+}
+
+#[cfg(test)]
+fn count(events: &[Event], kind: &str) -> usize {
+    events.iter().filter(|ev| ev.kind() == kind).count()
+}
+
+#[test]
+fn test_cannon_damage_and_death() {
+    use super::ai::CannonAction;
+    let mut game = test_game();
+    let mut actions = BTreeMap::new();
+    actions.insert(1, vec![
+        Action::CannonAction(CannonAction { bot_id: 0, pos: Position { x: 3, y: 0 } }),
+        Action::CannonAction(CannonAction { bot_id: 1, pos: Position { x: 3, y: 0 } })
+    ]);
+    let events = game.play_round(&actions);
+    // bot 2 takes two direct hits and dies, bot 3 two blasts
+    assert!(game.team(2).bots[0].hp == 0 && !game.team(2).bots[0].alive);
+    assert!(game.team(2).bots[1].hp == 1 && game.team(2).bots[1].alive);
+    assert!(count(&events[&1], "hit") == 4);
+    assert!(count(&events[&1], "die") == 1);
+    assert!(count(&events[&2], "damaged") == 2);
+    assert!(count(&events[&2], "noaction") == 2);
+    assert!(count(&events[&2], "die") == 1);
+    assert!(game.round_id == 1);
+    assert!(!game.is_over());
+}
+
+#[test]
+fn test_moves_are_checked() {
+    use super::ai::MoveAction;
+    let mut game = test_game();
+    let mut actions = BTreeMap::new();
+    actions.insert(1, vec![
+        // onto the asteroid
+        Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: -2, y: 0 } }),
+        // too far
+        Action::MoveAction(MoveAction { bot_id: 1, pos: Position { x: -4, y: -2 } }),
+        // not ours
+        Action::MoveAction(MoveAction { bot_id: 2, pos: Position { x: 2, y: 0 } })
+    ]);
+    actions.insert(2, vec![Action::MoveAction(MoveAction { bot_id: 2, pos: Position { x: 1, y: 0 } })]);
+    let events = game.play_round(&actions);
+    assert!(game.team(1).bots[0].pos == Position { x: -4, y: 0 });
+    assert!(game.team(1).bots[1].pos == Position { x: -4, y: 2 });
+    assert!(game.team(2).bots[0].pos == Position { x: 1, y: 0 });
+    assert!(count(&events[&1], "move") == 0);
+    assert!(count(&events[&2], "move") == 1);
+}
+
+#[test]
+fn test_radar_sight_and_winner() {
+    use super::ai::{RadarAction, CannonAction};
+    let mut game = test_game();
+    let first = game.first_events();
+    assert!(count(&first[&1], "seeAsteroid") == 0);
+    let mut actions = BTreeMap::new();
+    actions.insert(1, vec![Action::RadarAction(RadarAction { bot_id: 0, pos: Position { x: 4, y: 0 } })]);
+    let events = game.play_round(&actions);
+    assert!(count(&events[&1], "radarEcho") == 2);
+    assert!(count(&events[&2], "detected") == 2);
+    // the bots of team 2 stand next to each other but only see enemies
+    assert!(count(&events[&2], "see") == 0);
+
+    let mut actions = BTreeMap::new();
+    actions.insert(1, vec![
+        Action::CannonAction(CannonAction { bot_id: 0, pos: Position { x: 3, y: 0 } }),
+        Action::CannonAction(CannonAction { bot_id: 1, pos: Position { x: 4, y: 0 } })
+    ]);
+    game.play_round(&actions);
+    assert!(game.is_over());
+    assert!(game.winner() == Some(1));
+    assert!(game.end_message(2).winner_team_id == Some(1));
+    assert!(game.surviving_hp(1) == 6);
+}
@@ -0,0 +1,254 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::mpsc::{channel, Sender, Receiver as ChannelReceiver};
+use std::thread;
+use std::time::Duration;
+
+use websocket::{Server, Receiver, Sender as WSSender};
+use websocket::Message as WSMessage;
+
+use super::GameConfig;
+use super::ai::{self, Action};
+use super::incoming::Message;
+use super::outgoing::OutgoingMessage;
+use super::rules::Game;
+
+enum Input {
+    Message(u32, OutgoingMessage),
+    Timeout(u32),
+    Disconnected(u32)
+}
+
+fn send(outputs: &BTreeMap<u32, Sender<String>>, team_id: u32, message: Message) {
+    if let Some(output) = outputs.get(&team_id) {
+        let _ = output.send(message.to_json());
+    }
+}
+
+/// How many teams have answered or can't answer anymore. A team that answered
+/// and then disconnected is only counted once.
+fn settled<T>(answers: &BTreeMap<u32, T>, disconnected: &BTreeSet<u32>) -> usize {
+    answers.keys().chain(disconnected.iter()).collect::<BTreeSet<_>>().len()
+}
+
+/// A stand-in for the Tyckiting server, run by the `tyckiting-server` binary:
+/// waits for `team_count` clients on `port`, plays one game between them by
+/// the rules in `rules` and exits.
+/// A round ends when every connected team has sent its actions or after
+/// `config.loop_time` milliseconds, whichever comes first.
+pub fn run(port: u32, team_count: usize, config: GameConfig, seed: u64) -> io::Result<()> {
+    let server = try!(Server::bind(&format!("0.0.0.0:{}", port)[..]));
+    println!("Waiting for {} teams on port {}", team_count, port);
+
+    let (input_sender, inputs) = channel();
+    let mut outputs: BTreeMap<u32, Sender<String>> = BTreeMap::new();
+    for connection in server {
+        let request = match connection.and_then(|c| c.read_request()) {
+            Ok(request) => request,
+            Err(e) => {
+                println!("Couldn't accept a client: {:?}", e);
+                continue;
+            }
+        };
+        let client = match request.accept().send() {
+            Ok(client) => client,
+            Err(e) => {
+                println!("Couldn't accept a client: {:?}", e);
+                continue;
+            }
+        };
+        let team_id = outputs.len() as u32 + 1;
+        let (mut sender, mut receiver) = client.split();
+
+        let (output, frames) = channel::<String>();
+        thread::spawn(move || {
+            for frame in frames.iter() {
+                if sender.send_message(WSMessage::Text(frame)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let input = input_sender.clone();
+        thread::spawn(move || {
+            for message in receiver.incoming_messages() {
+                match message {
+                    Ok(WSMessage::Text(text)) => match OutgoingMessage::parse(&text) {
+                        Ok(msg) => if input.send(Input::Message(team_id, msg)).is_err() { return; },
+                        Err(e) => println!("Team {} sent something unreadable ({}): {}", team_id, e, text)
+                    },
+                    Ok(WSMessage::Close(_)) | Err(_) => break,
+                    _ => ()
+                }
+            }
+            let _ = input.send(Input::Disconnected(team_id));
+        });
+
+        let _ = output.send(Message::ConnectedMessage(super::incoming::ConnectedMessage {
+            team_id: team_id, config: config.clone()
+        }).to_json());
+        outputs.insert(team_id, output);
+        println!("Team {} connected", team_id);
+        if outputs.len() == team_count {
+            break;
+        }
+    }
+
+    host_game(config, seed, team_count, &inputs, input_sender, &outputs);
+    // dropping the outputs lets the writer threads finish sending the end
+    drop(outputs);
+    thread::sleep(Duration::from_millis(500));
+    Ok(())
+}
+
+/// Waits for the connected teams to join, plays the game and tells them how
+/// it ended. What the teams send comes in on `inputs`, frames for them go to
+/// `outputs` and round timeouts are sent through `timer`.
+fn host_game(config: GameConfig, seed: u64, team_count: usize, inputs: &ChannelReceiver<Input>,
+             timer: Sender<Input>, outputs: &BTreeMap<u32, Sender<String>>) -> Game {
+    let mut names: BTreeMap<u32, String> = BTreeMap::new();
+    let mut disconnected: BTreeSet<u32> = BTreeSet::new();
+    while settled(&names, &disconnected) < team_count {
+        match inputs.recv() {
+            Ok(Input::Message(team_id, OutgoingMessage::Join { team_name })) => {
+                println!("Team {} is {}", team_id, team_name);
+                names.insert(team_id, team_name);
+            },
+            Ok(Input::Disconnected(team_id)) => { disconnected.insert(team_id); },
+            Ok(_) => (),
+            Err(_) => break
+        }
+    }
+    let team_names: Vec<String> = (1..team_count as u32 + 1)
+        .map(|team_id| names.get(&team_id).cloned().unwrap_or_else(|| format!("Team {}", team_id)))
+        .collect();
+
+    let mut game = Game::new(config, &team_names, &mut ai::seeded_rng(seed));
+    for team_id in game.team_ids() {
+        send(outputs, team_id, Message::StartMessage(game.start_message(team_id)));
+    }
+
+    let mut events = game.first_events();
+    while !game.is_over() {
+        let round_id = game.round_id;
+        for team_id in game.team_ids() {
+            let team_events = events.remove(&team_id).unwrap_or(Vec::new());
+            send(outputs, team_id, Message::EventsMessage(game.events_message(team_id, team_events)));
+        }
+
+        let round_timer = timer.clone();
+        let loop_time = game.config.loop_time as u64;
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(loop_time));
+            let _ = round_timer.send(Input::Timeout(round_id));
+        });
+
+        let mut actions: BTreeMap<u32, Vec<Action>> = BTreeMap::new();
+        while settled(&actions, &disconnected) < team_count {
+            match inputs.recv() {
+                Ok(Input::Message(team_id, OutgoingMessage::Actions { round_id: id, actions: team_actions })) => {
+                    if id == round_id {
+                        actions.insert(team_id, team_actions);
+                    }
+                },
+                Ok(Input::Message(..)) => (),
+                Ok(Input::Timeout(id)) => if id == round_id { break; },
+                Ok(Input::Disconnected(team_id)) => {
+                    println!("Team {} disconnected", team_id);
+                    disconnected.insert(team_id);
+                },
+                Err(_) => break
+            }
+        }
+        events = game.play_round(&actions);
+    }
+
+    match game.winner() {
+        Some(winner) => println!("{} won after {} rounds", game.team(winner).name, game.round_id),
+        None => println!("No winner after {} rounds", game.round_id)
+    }
+    for team_id in game.team_ids() {
+        send(outputs, team_id, Message::EndMessage(game.end_message(team_id)));
+    }
+    game
+}
+
+#[cfg(test)]
+fn next_events(frames: &ChannelReceiver<String>) -> Option<super::incoming::EventsMessage> {
+    for frame in frames.iter() {
+        match super::incoming::parse_message(&frame) {
+            Ok(Message::EventsMessage(msg)) => return Some(msg),
+            Ok(Message::EndMessage(_)) => return None,
+            _ => ()
+        }
+    }
+    None
+}
+
+#[test]
+fn test_round_waits_for_teams_still_connected() {
+    use super::ai::MoveAction;
+    use super::Position;
+
+    let config = GameConfig { max_count: 3, loop_time: 10000, asteroids: Some(0), ..super::rules::default_config() };
+    let (input_sender, inputs) = channel();
+    let mut outputs = BTreeMap::new();
+    let mut frames = Vec::new();
+    for team_id in 1..3 {
+        let (output, team_frames) = channel();
+        outputs.insert(team_id, output);
+        frames.push(team_frames);
+    }
+    let timer = input_sender.clone();
+    let host = thread::spawn(move || host_game(config, 1, 2, &inputs, timer, &outputs).round_id);
+
+    for team_id in 1..3 {
+        let join = OutgoingMessage::Join { team_name: format!("Team {}", team_id) };
+        input_sender.send(Input::Message(team_id, join)).unwrap();
+    }
+    // team 2 answers the first round and leaves before team 1 has answered
+    let first = next_events(&frames[1]).unwrap();
+    input_sender.send(Input::Message(2, OutgoingMessage::Actions { round_id: first.round_id, actions: vec![] })).unwrap();
+    input_sender.send(Input::Disconnected(2)).unwrap();
+
+    let mut rounds = 0;
+    let mut moved = Vec::new();
+    while let Some(msg) = next_events(&frames[0]) {
+        for event in msg.events.iter() {
+            if let super::incoming::Event::MoveEvent(ref me) = *event {
+                moved.push(me.bot_id);
+            }
+        }
+        let bot = msg.you.bots[0].clone();
+        let to = if bot.pos.distance(Position { x: 0, y: 0 }) < msg.config.field_radius {
+            bot.pos + Position { x: 1, y: 0 }
+        } else {
+            bot.pos - Position { x: 1, y: 0 }
+        };
+        let actions = vec![Action::MoveAction(MoveAction { bot_id: bot.bot_id, pos: to })];
+        input_sender.send(Input::Message(1, OutgoingMessage::Actions { round_id: msg.round_id, actions: actions })).unwrap();
+        rounds += 1;
+    }
+    assert!(host.join().unwrap() == 3);
+    assert!(rounds == 3);
+    // team 1's move in the first round was played, not dropped by closing the round early
+    assert!(moved.len() == 2);
+}
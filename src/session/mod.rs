@@ -0,0 +1,223 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use websocket::{Receiver, Sender};
+use websocket::Message as WSMessage;
+use websocket::client::request::Url;
+use websocket::Client;
+
+use super::incoming::{self, Message, Event};
+use super::hexset::HexSet;
+use super::outgoing::OutgoingMessage;
+use super::watchdog::Watchdog;
+use super::recorder::Recorder;
+use super::validation::{self, ValidationMode};
+
+/// Why a session ended before the server sent the end of the game.
+#[derive(Debug)]
+pub enum SessionError {
+    /// Couldn't open the websocket at all
+    Connect(String),
+    /// The connection went away before a game had started
+    Disconnected(String),
+    /// The connection went away in the middle of a game
+    Dropped(String)
+}
+
+impl SessionError {
+    /// Whether the session got as far as playing a game before it failed.
+    pub fn joined(&self) -> bool {
+        match *self {
+            SessionError::Dropped(_) => true,
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionError::Connect(ref reason) => write!(f, "couldn't connect: {}", reason),
+            SessionError::Disconnected(ref reason) => write!(f, "disconnected: {}", reason),
+            SessionError::Dropped(ref reason) => write!(f, "dropped from the game: {}", reason)
+        }
+    }
+}
+
+/// How a finished game went, as told by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameOutcome {
+    pub our_team_id: u32,
+    pub winner_team_id: Option<u32>,
+    pub winner_name: Option<String>
+}
+
+impl GameOutcome {
+    pub fn won(&self) -> bool {
+        self.winner_team_id == Some(self.our_team_id)
+    }
+}
+
+/// Settings that stay the same across reconnects.
+pub struct SessionOptions {
+    pub team_name: String,
+    pub validation_mode: ValidationMode,
+    /// Kept in reserve from the server's loop time when waiting for the AI
+    pub margin_ms: u32
+}
+
+/// Connects to `url`, joins and plays until the server ends the game.
+/// Prints the event kinds the server sent that this client doesn't know,
+/// once per game.
+fn report_unknown_events(kinds: &BTreeSet<String>) {
+    if !kinds.is_empty() {
+        let kinds: Vec<&str> = kinds.iter().map(|k| k.as_ref()).collect();
+        println!("Server sent event kinds this client doesn't know: {}", kinds.connect(", "));
+    }
+}
+
+/// The error for a connection that went away, after reporting what was
+/// collected of the game so far.
+fn lost(reason: String, joined: bool, unknown_event_kinds: &BTreeSet<String>) -> SessionError {
+    report_unknown_events(unknown_event_kinds);
+    if joined { SessionError::Dropped(reason) } else { SessionError::Disconnected(reason) }
+}
+
+pub fn play(url: &str, options: &SessionOptions, ai: &mut Watchdog, mut recorder: Option<&mut Recorder>) -> Result<GameOutcome, SessionError> {
+    let url = try!(Url::parse(url).map_err(|e| SessionError::Connect(format!("{:?}", e))));
+    let request = try!(Client::connect(url).map_err(|e| SessionError::Connect(format!("{:?}", e))));
+    let response = try!(request.send().map_err(|e| SessionError::Connect(format!("{:?}", e))));
+    try!(response.validate().map_err(|e| SessionError::Connect(format!("{:?}", e))));
+
+    let (mut sender, mut receiver) = response.begin().split();
+
+    let mut unknown_event_kinds: BTreeSet<String> = BTreeSet::new();
+    let mut team_names: BTreeMap<u32, String> = BTreeMap::new();
+    let mut joined = false;
+    // where the bots can't be moved to when the AI is too slow or misses one
+    let mut asteroids = HexSet::new(0);
+    for message in receiver.incoming_messages() {
+        let message = match message {
+            Ok(m) => m,
+            Err(e) => return Err(lost(format!("{:?}", e), joined, &unknown_event_kinds))
+        };
+        match message {
+            WSMessage::Close(_) => {
+                return Err(lost("server closed the connection".to_string(), joined, &unknown_event_kinds));
+            }
+            WSMessage::Text(msg) => {
+                let message = match incoming::parse_message(&msg) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        println!("Couldn't parse message ({}), skipping: {}", e, msg);
+                        if let Some(ref mut recorder) = recorder {
+                            recorder.inbound(&msg, None, false);
+                        }
+                        continue;
+                    }
+                };
+                if let Some(ref mut recorder) = recorder {
+                    if let Message::StartMessage(ref start) = message {
+                        let opponents: Vec<String> = start.other_teams.iter().map(|team| team.name.clone()).collect();
+                        if let Err(e) = recorder.start_game(&opponents) {
+                            println!("Couldn't start recording: {}", e);
+                        }
+                    }
+                    let round_id = match message {
+                        Message::EventsMessage(ref events) => Some(events.round_id),
+                        _ => None
+                    };
+                    recorder.inbound(&msg, round_id, true);
+                }
+                match message {
+                    Message::ConnectedMessage(msg) => {
+                        let join = OutgoingMessage::Join { team_name: options.team_name.clone() }.to_json();
+                        if let Some(ref mut recorder) = recorder {
+                            recorder.outbound(&join, None);
+                        }
+                        let _ = sender.send_message(WSMessage::Text(join));
+                        ai.on_connected(msg.team_id, msg.config);
+                    },
+                    Message::StartMessage(msg) => {
+                        // the server sends `connected` to anyone who opens a
+                        // websocket, only a started game counts as joined
+                        joined = true;
+                        asteroids = HexSet::new(msg.config.field_radius);
+                        team_names.insert(msg.you.team_id, msg.you.name.clone());
+                        for team in msg.other_teams.iter() {
+                            team_names.insert(team.team_id, team.name.clone());
+                        }
+                        ai.on_game_start(msg);
+                    },
+                    Message::EndMessage(msg) => {
+                        if let Some(ref mut recorder) = recorder {
+                            recorder.end_game();
+                        }
+                        report_unknown_events(&unknown_event_kinds);
+                        println!("Thanks for playing!");
+                        match msg.winner_team_id {
+                            Some(winner) => println!("Winner was {}, you are: {}", winner, msg.you.team_id),
+                            None => println!("There was no winner :(")
+                        };
+                        let outcome = GameOutcome {
+                            our_team_id: msg.you.team_id,
+                            winner_team_id: msg.winner_team_id,
+                            winner_name: msg.winner_team_id.and_then(|winner| team_names.get(&winner).cloned())
+                        };
+                        ai.on_game_end(msg);
+                        return Ok(outcome);
+                    },
+                    Message::EventsMessage(msg) => {
+                        joined = true;
+                        if asteroids.radius() != msg.config.field_radius {
+                            asteroids = HexSet::new(msg.config.field_radius);
+                        }
+                        for event in msg.events.iter() {
+                            match *event {
+                                Event::Unknown { ref kind, .. } => { unknown_event_kinds.insert(kind.clone()); },
+                                Event::SeeAsteroidEvent(ref ev) => { asteroids.insert(ev.pos); },
+                                _ => {}
+                            }
+                        }
+                        let (round_id, config, you) = (msg.round_id, msg.config.clone(), msg.you.clone());
+                        ai.set_budget(config.loop_time, options.margin_ms);
+                        let (actions, violations) = validation::validate(ai.respond(msg, &asteroids), &config, &you, &asteroids, options.validation_mode);
+                        for violation in violations.iter() {
+                            println!("Round {}: {}", round_id, violation);
+                        }
+                        ai.on_actions_sent(round_id, actions.clone());
+                        let actions = OutgoingMessage::Actions {
+                            round_id: round_id,
+                            actions: actions
+                        }.to_json();
+                        if let Some(ref mut recorder) = recorder {
+                            recorder.outbound(&actions, Some(round_id));
+                        }
+                        let _ = sender.send_message(WSMessage::Text(actions));
+                    },
+                    Message::Unknown { kind, .. } => println!("Ignoring unknown message type {}: {}", kind, msg)
+                }
+            }
+            _ => println!("Got {:?}. Don't know what to do with it", message)
+        }
+    }
+    Err(lost("connection ended before the game did".to_string(), joined, &unknown_event_kinds))
+}
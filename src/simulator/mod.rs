@@ -0,0 +1,115 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::BTreeMap;
+
+use super::GameConfig;
+use super::ai::{self, Ai};
+use super::rules::Game;
+
+#[cfg(test)] use test::Bencher;
+
+/// How a simulated game ended. Teams are numbered like the AIs that played
+/// them, starting from 1.
+#[derive(Debug, PartialEq)]
+pub struct GameResult {
+    pub winner: Option<u32>,
+    pub rounds: u32,
+    pub surviving_hp: Vec<i32>
+}
+
+/// Plays one game between `ais` without a server. Each AI sees exactly the
+/// hooks and events it would get from a live game; `seed` decides where the
+/// bots and asteroids are placed.
+pub fn simulate(config: GameConfig, ais: &mut [Box<Ai + Send>], seed: u64) -> GameResult {
+    let team_names: Vec<String> = (0..ais.len()).map(|i| format!("Team {}", i + 1)).collect();
+    let mut game = Game::new(config, &team_names, &mut ai::seeded_rng(seed));
+    let team_ids = game.team_ids();
+
+    for (ai, &team_id) in ais.iter_mut().zip(team_ids.iter()) {
+        // thousands of games would drown in per-round output
+        ai.set_verbose(false);
+        let connected = game.connected_message(team_id);
+        ai.on_connected(connected.team_id, connected.config);
+        ai.on_game_start(game.start_message(team_id));
+    }
+
+    let mut events = game.first_events();
+    while !game.is_over() {
+        let mut actions = BTreeMap::new();
+        for (ai, &team_id) in ais.iter_mut().zip(team_ids.iter()) {
+            let msg = game.events_message(team_id, events.remove(&team_id).unwrap_or(Vec::new()));
+            let round_id = msg.round_id;
+            ai.on_round(round_id);
+            ai.set_state(msg.config, msg.you, msg.other_teams);
+            let team_actions = ai.respond(msg.events);
+            ai.on_actions_sent(round_id, &team_actions);
+            actions.insert(team_id, team_actions);
+        }
+        events = game.play_round(&actions);
+    }
+
+    for (ai, &team_id) in ais.iter_mut().zip(team_ids.iter()) {
+        ai.on_game_end(game.end_message(team_id));
+    }
+    GameResult {
+        winner: game.winner(),
+        rounds: game.round_id,
+        surviving_hp: team_ids.iter().map(|&team_id| game.surviving_hp(team_id)).collect()
+    }
+}
+
+#[cfg(test)]
+fn random_ais(seed: u64) -> Vec<Box<Ai + Send>> {
+    vec![ai::from_name("random", ai::seeded_rng(seed)).unwrap(),
+         ai::from_name("random", ai::seeded_rng(seed + 1)).unwrap()]
+}
+
+#[test]
+fn test_simulate_is_deterministic() {
+    let config = GameConfig { field_radius: 8, max_count: 50, ..super::rules::default_config() };
+    let first = simulate(config.clone(), &mut random_ais(7), 3);
+    let second = simulate(config, &mut random_ais(7), 3);
+    assert!(first == second);
+    assert!(first.rounds > 0 && first.rounds <= 50);
+    assert!(first.surviving_hp.len() == 2);
+    match first.winner {
+        Some(winner) => assert!(first.surviving_hp[winner as usize - 1] > 0),
+        None => assert!(first.rounds == 50 || first.surviving_hp.iter().all(|&hp| hp == 0))
+    }
+}
+
+#[test]
+fn test_simulate_small_field() {
+    // no room for two radars that don't overlap, the AIs have to do something else
+    let config = GameConfig { field_radius: 4, max_count: 100, asteroids: Some(0), ..super::rules::default_config() };
+    for seed in 0..5 {
+        let result = simulate(config.clone(), &mut random_ais(seed), seed);
+        assert!(result.rounds <= 100);
+    }
+}
+
+#[bench]
+fn bench_simulate_game(b: &mut Bencher) {
+    let config = super::rules::default_config();
+    let mut seed = 0;
+    b.iter(|| {
+        seed += 1;
+        simulate(config.clone(), &mut random_ais(seed), seed)
+    });
+}
@@ -0,0 +1,148 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate rand;
+
+use std::cmp::min;
+use std::thread;
+use std::time::Duration;
+
+use self::rand::Rng;
+
+use super::session::SessionError;
+
+/// Exponential backoff with jitter between reconnection attempts.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial_ms: u32,
+    max_ms: u32,
+    attempt: u32
+}
+
+impl Backoff {
+    pub fn new(initial_ms: u32, max_ms: u32) -> Backoff {
+        Backoff { initial_ms: initial_ms, max_ms: max_ms, attempt: 0 }
+    }
+
+    /// The next delay is picked at random from the upper half of the current
+    /// window, so that clients restarted together don't reconnect in lockstep.
+    pub fn next_delay<R: Rng>(&mut self, rng: &mut R) -> u32 {
+        let window = min(self.max_ms as u64, (self.initial_ms as u64) << min(self.attempt, 20)) as u32;
+        self.attempt += 1;
+        window / 2 + rng.gen_range(0, window / 2 + 1)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Runs `session` until it finishes a game, retrying failed connections.
+/// `session` gets the attempt number, starting from 1. Returns what the
+/// finished game returned, or the last error once `max_attempts` consecutive
+/// attempts have failed. A session that was dropped from a game that had
+/// started begins the count and the backoff over.
+pub fn supervise<T, F>(max_attempts: u32, mut backoff: Backoff, mut session: F) -> Result<T, SessionError>
+    where F: FnMut(u32) -> Result<T, SessionError> {
+    let mut rng = rand::thread_rng();
+    let mut attempt = 1;
+    loop {
+        match session(attempt) {
+            Ok(result) => return Ok(result),
+            Err(e) => {
+                if e.joined() {
+                    attempt = 1;
+                    backoff.reset();
+                }
+                if attempt >= max_attempts {
+                    return Err(e);
+                }
+                let delay = backoff.next_delay(&mut rng);
+                println!("Attempt {}/{} failed, {}. Retrying in {} ms", attempt, max_attempts, e, delay);
+                thread::sleep(Duration::from_millis(delay as u64));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backoff_grows_and_caps() {
+    let mut rng = rand::thread_rng();
+    let mut backoff = Backoff::new(100, 1000);
+    let delays: Vec<u32> = (0..8).map(|_| backoff.next_delay(&mut rng)).collect();
+    assert!(delays[0] >= 50 && delays[0] <= 100);
+    assert!(delays[2] >= 200 && delays[2] <= 400);
+    assert!(delays.iter().all(|&d| d <= 1000));
+    assert!(delays[7] >= 500);
+    backoff.reset();
+    assert!(backoff.next_delay(&mut rng) <= 100);
+}
+
+#[test]
+fn test_supervise_gives_up() {
+    let mut attempts = 0;
+    let result: Result<(), _> = supervise(3, Backoff::new(0, 0), |attempt| {
+        attempts = attempt;
+        Err(SessionError::Connect("refused".to_string()))
+    });
+    assert!(attempts == 3);
+    match result {
+        Err(SessionError::Connect(ref reason)) => assert!(reason == "refused"),
+        other => panic!("Expected connection error, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_supervise_gives_up_when_games_never_start() {
+    // a server that accepts the connection and drops it before the start
+    let mut attempts = 0;
+    let result: Result<(), _> = supervise(4, Backoff::new(0, 0), |attempt| {
+        attempts = attempt;
+        Err(SessionError::Disconnected("server closed the connection".to_string()))
+    });
+    assert!(attempts == 4);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_supervise_retries_until_success() {
+    let result = supervise(5, Backoff::new(0, 0), |attempt| {
+        if attempt < 3 { Err(SessionError::Disconnected("dropped".to_string())) } else { Ok(attempt) }
+    });
+    assert!(result.unwrap() == 3);
+}
+
+#[test]
+fn test_supervise_starts_over_after_joining() {
+    // drop, reconnect, drop: three failures with max_attempts 3, but never
+    // three in a row without having joined a game
+    let mut calls = 0;
+    let mut attempts = Vec::new();
+    let result = supervise(3, Backoff::new(0, 0), |attempt| {
+        calls += 1;
+        attempts.push(attempt);
+        match calls {
+            2 | 4 => Err(SessionError::Dropped("dropped".to_string())),
+            5 => Ok(calls),
+            _ => Err(SessionError::Connect("refused".to_string()))
+        }
+    });
+    assert!(result.unwrap() == 5);
+    assert!(attempts == vec![1, 2, 2, 3, 2]);
+}
@@ -0,0 +1,233 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate rand;
+
+use std::cmp::{max, min};
+
+use self::rand::Rng;
+
+use super::GameConfig;
+use super::ai::{self, AiEntry, Params};
+use super::simulator;
+
+/// Rating every AI starts from and the ratings are centred on.
+const BASE_ELO: f64 = 1500.0;
+/// Two-sided 95% quantile of the normal distribution
+const Z_95: f64 = 1.96;
+
+/// One AI's results over a tournament.
+#[derive(Debug, Clone)]
+pub struct Standing {
+    pub name: String,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub total_hp: i32,
+    pub elo: f64,
+    pub elo_low: f64,
+    pub elo_high: f64
+}
+
+impl Standing {
+    fn new(name: &str) -> Standing {
+        Standing { name: name.to_string(), wins: 0, draws: 0, losses: 0, total_hp: 0, elo: BASE_ELO, elo_low: BASE_ELO, elo_high: BASE_ELO }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn average_hp(&self) -> f64 {
+        if self.games() == 0 { 0.0 } else { self.total_hp as f64 / self.games() as f64 }
+    }
+}
+
+/// The result of one game from the first AI's point of view: 1 for a win,
+/// 0.5 for a draw and 0 for a loss.
+struct Outcome {
+    first: usize,
+    second: usize,
+    score: f64
+}
+
+/// A copy of `base` with a random field size and number of asteroids, so that
+/// an AI can't win a tournament by being tuned to a single map. The field
+/// stays big enough for two radars that don't overlap.
+fn vary_config<R: Rng>(base: &GameConfig, rng: &mut R) -> GameConfig {
+    let smallest = min(max(base.field_radius - 4, 2 * base.radar + 1), base.field_radius);
+    let field_radius = rng.gen_range(smallest, base.field_radius + 1);
+    let asteroids = base.asteroids.map(|count| rng.gen_range(0, count + 1));
+    GameConfig { field_radius: field_radius, asteroids: asteroids, ..base.clone() }
+}
+
+/// Plays every pair of `ais` against each other `games` times, switching
+/// sides every game, and returns the standings best first.
+pub fn run(ais: &[(&'static AiEntry, Params)], games: u32, base: &GameConfig, seed: u64) -> Vec<Standing> {
+    let mut rng = ai::seeded_rng(seed);
+    let mut standings: Vec<Standing> = ais.iter().map(|&(entry, _)| Standing::new(entry.name)).collect();
+    let mut outcomes = Vec::new();
+
+    for first in 0..ais.len() {
+        for second in first + 1..ais.len() {
+            for game in 0..games {
+                let config = vary_config(base, &mut rng);
+                let game_seed: u64 = rng.gen();
+                let sides = if game % 2 == 0 { [first, second] } else { [second, first] };
+                let build = |side: usize, rng_seed: u64| {
+                    let (entry, ref params) = ais[sides[side]];
+                    (entry.build)(ai::seeded_rng(rng_seed), params)
+                };
+                let mut players = vec![build(0, game_seed), build(1, game_seed ^ 1)];
+                let result = simulator::simulate(config, &mut players, game_seed);
+                for (team, &index) in sides.iter().enumerate() {
+                    let standing = &mut standings[index];
+                    standing.total_hp += result.surviving_hp[team];
+                    match result.winner {
+                        Some(winner) if winner as usize == team + 1 => standing.wins += 1,
+                        Some(_) => standing.losses += 1,
+                        None => standing.draws += 1
+                    }
+                }
+                let score = match result.winner {
+                    Some(winner) if winner as usize == 1 => 1.0,
+                    Some(_) => 0.0,
+                    None => 0.5
+                };
+                outcomes.push(Outcome { first: sides[0], second: sides[1], score: score });
+            }
+        }
+    }
+
+    rate(&mut standings, &outcomes);
+    standings.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap());
+    standings
+}
+
+/// The rating difference at which the expected score is `score`.
+fn elo_difference(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Sets every AI's rating to the one that explains its score against the
+/// ratings of the opponents it met, iterated until the ratings settle. The
+/// interval comes from the standard error of the score. Scores of exactly 0
+/// or 1 are pulled in by half a game so the ratings stay finite.
+fn rate(standings: &mut [Standing], outcomes: &[Outcome]) {
+    let mut ratings = vec![BASE_ELO; standings.len()];
+    let mut scores = vec![Vec::new(); standings.len()];
+    let mut opponents = vec![Vec::new(); standings.len()];
+    for outcome in outcomes.iter() {
+        scores[outcome.first].push(outcome.score);
+        scores[outcome.second].push(1.0 - outcome.score);
+        opponents[outcome.first].push(outcome.second);
+        opponents[outcome.second].push(outcome.first);
+    }
+
+    let clamp = |score: f64, games: f64| score.max(0.5 / games).min(1.0 - 0.5 / games);
+    let mean = |values: &[f64]| values.iter().fold(0.0, |sum, v| sum + v) / values.len() as f64;
+
+    for _ in 0..100 {
+        for i in 0..standings.len() {
+            if scores[i].is_empty() {
+                continue;
+            }
+            let games = scores[i].len() as f64;
+            let opponent_ratings: Vec<f64> = opponents[i].iter().map(|&j| ratings[j]).collect();
+            ratings[i] = mean(&opponent_ratings) + elo_difference(clamp(mean(&scores[i]), games));
+        }
+        let offset = BASE_ELO - mean(&ratings);
+        for rating in ratings.iter_mut() {
+            *rating += offset;
+        }
+    }
+
+    for (i, standing) in standings.iter_mut().enumerate() {
+        standing.elo = ratings[i];
+        if scores[i].is_empty() {
+            continue;
+        }
+        let games = scores[i].len() as f64;
+        let score = mean(&scores[i]);
+        let variance = scores[i].iter().fold(0.0, |sum, s| sum + (s - score) * (s - score)) / games;
+        let error = Z_95 * (variance / games).sqrt();
+        let opponent_ratings: Vec<f64> = opponents[i].iter().map(|&j| ratings[j]).collect();
+        let opponent_mean = mean(&opponent_ratings);
+        standing.elo_low = opponent_mean + elo_difference(clamp(score - error, games));
+        standing.elo_high = opponent_mean + elo_difference(clamp(score + error, games));
+    }
+}
+
+pub fn print_standings(standings: &[Standing]) {
+    println!("{:>4}  {:<20} {:>5} {:>5} {:>5} {:>7} {:>6}  {}", "Rank", "AI", "W", "D", "L", "Avg HP", "Elo", "95% interval");
+    for (i, standing) in standings.iter().enumerate() {
+        println!("{:>4}  {:<20} {:>5} {:>5} {:>5} {:>7.2} {:>6.0}  {:.0}..{:.0}",
+                 i + 1, standing.name, standing.wins, standing.draws, standing.losses,
+                 standing.average_hp(), standing.elo, standing.elo_low, standing.elo_high);
+    }
+}
+
+#[test]
+fn test_rate() {
+    let mut standings = vec![Standing::new("strong"), Standing::new("weak"), Standing::new("even")];
+    let mut outcomes = Vec::new();
+    for i in 0..20 {
+        // strong always beats weak and wins three in four against even,
+        // even wins three in four against weak
+        outcomes.push(Outcome { first: 0, second: 1, score: 1.0 });
+        outcomes.push(Outcome { first: 2, second: 1, score: if i < 15 { 1.0 } else { 0.0 } });
+        outcomes.push(Outcome { first: 0, second: 2, score: if i < 15 { 1.0 } else { 0.0 } });
+    }
+    rate(&mut standings, &outcomes);
+    let total = standings.iter().fold(0.0, |sum, s| sum + s.elo);
+    assert!((total / 3.0 - BASE_ELO).abs() < 0.001);
+    assert!(standings[0].elo > standings[2].elo && standings[2].elo > standings[1].elo);
+    assert!((standings[2].elo - BASE_ELO).abs() < 0.001);
+    for standing in standings.iter() {
+        assert!(standing.elo_low <= standing.elo && standing.elo <= standing.elo_high);
+        assert!(standing.elo.is_finite());
+    }
+}
+
+#[test]
+fn test_run_counts_every_game() {
+    let base = GameConfig { field_radius: 9, max_count: 30, ..super::rules::default_config() };
+    let random = ai::find("random").unwrap();
+    let ais = vec![(random, random.default_params()); 3];
+    let standings = run(&ais, 2, &base, 11);
+    assert!(standings.len() == 3);
+    for standing in standings.iter() {
+        assert!(standing.games() == 4);
+    }
+    let wins = standings.iter().fold(0, |sum, s| sum + s.wins);
+    let losses = standings.iter().fold(0, |sum, s| sum + s.losses);
+    assert!(wins == losses);
+}
+
+#[test]
+fn test_vary_config_leaves_room_for_radars() {
+    let mut rng = ai::seeded_rng(5);
+    let base = GameConfig { field_radius: 9, ..super::rules::default_config() };
+    for _ in 0..100 {
+        let config = vary_config(&base, &mut rng);
+        assert!(config.field_radius > 2 * base.radar && config.field_radius <= base.field_radius);
+    }
+    // a base field that is already small isn't made any bigger
+    let small = GameConfig { field_radius: 5, ..base };
+    assert!(vary_config(&small, &mut rng).field_radius == 5);
+}
@@ -0,0 +1,283 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::Position;
+use super::board::Board;
+use super::hexset::HexSet;
+
+/// Where the enemy bots probably are. Every hex holds the expected number of
+/// enemy bots on it, so the whole field adds up to the number of enemies
+/// still alive.
+///
+/// Each round starts with `predict`, which spreads the belief by one enemy
+/// move, and is followed by what the round's events told us: hexes we saw or
+/// scanned without finding anything, enemies we saw or got an echo from, and
+/// where our cannons hit or missed.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    board: Board,
+    move_: u32,
+    enemies: u32,
+    belief: Vec<f64>,
+    asteroids: HexSet,
+    // what this round told us for sure, forgotten by the next `predict`
+    cleared: HexSet,
+    spotted: HexSet
+}
+
+impl Tracker {
+    /// A tracker that knows nothing yet: `enemies` bots, each anywhere on the board.
+    pub fn new(board: &Board, move_: u32, enemies: u32) -> Tracker {
+        let mut tracker = Tracker {
+            board: board.clone(),
+            move_: move_,
+            enemies: enemies,
+            belief: vec![1.0; board.len()],
+            asteroids: HexSet::for_board(board),
+            cleared: HexSet::for_board(board),
+            spotted: HexSet::for_board(board)
+        };
+        tracker.normalize();
+        tracker
+    }
+
+    /// The expected number of enemy bots at `pos`.
+    pub fn belief(&self, pos: Position) -> f64 {
+        self.board.index(pos).map_or(0.0, |i| self.belief[i])
+    }
+
+    /// The expected number of enemy bots within `radius` of `center`.
+    pub fn belief_within(&self, center: Position, radius: u32) -> f64 {
+        self.board.clip(center.positions_within(radius)).iter()
+            .fold(0.0, |sum, pos| sum + self.belief(*pos))
+    }
+
+    /// Enemies die, the belief shrinks with them.
+    pub fn set_enemies(&mut self, enemies: u32) {
+        if enemies != self.enemies {
+            self.enemies = enemies;
+            self.normalize();
+        }
+    }
+
+    /// Bots can't stand on asteroids, so no enemy is there.
+    pub fn add_asteroid(&mut self, pos: Position) {
+        if self.asteroids.insert(pos) {
+            self.normalize();
+        }
+    }
+
+    /// Starts a new round: every enemy may have moved up to `move_` hexes to
+    /// anywhere that isn't an asteroid.
+    pub fn predict(&mut self) {
+        let mut next = vec![0.0; self.belief.len()];
+        for (i, &mass) in self.belief.iter().enumerate() {
+            if mass == 0.0 {
+                continue;
+            }
+            let from = self.board.position(i);
+            let targets: Vec<usize> = self.board.clip(from.positions_within(self.move_)).into_iter()
+                .filter(|pos| !self.asteroids.contains(*pos))
+                .filter_map(|pos| self.board.index(pos))
+                .collect();
+            if targets.is_empty() {
+                next[i] += mass;
+                continue;
+            }
+            let share = mass / targets.len() as f64;
+            for &j in targets.iter() {
+                next[j] += share;
+            }
+        }
+        self.belief = next;
+        self.cleared.clear();
+        self.spotted.clear();
+        self.normalize();
+    }
+
+    /// Nothing was found within `radius` of `center`, by a radar or by a bot's
+    /// sight. Hexes where an enemy was spotted this round are left alone.
+    pub fn clear_area(&mut self, center: Position, radius: u32) {
+        for pos in self.board.clip(center.positions_within(radius)).into_iter() {
+            if !self.spotted.contains(pos) {
+                self.cleared.insert(pos);
+            }
+        }
+        self.normalize();
+    }
+
+    /// An enemy is at `pos`, from a radar echo or a bot seeing it.
+    pub fn spotted(&mut self, pos: Position) {
+        if self.board.contains(pos) {
+            self.cleared.remove(pos);
+            self.spotted.insert(pos);
+            self.normalize();
+        }
+    }
+
+    /// Our cannon hit an enemy within `radius` of `target`, so at least one
+    /// enemy is there. Missing is `clear_area`.
+    pub fn hit_area(&mut self, target: Position, radius: u32) {
+        let area: Vec<usize> = self.board.clip(target.positions_within(radius)).into_iter()
+            .filter(|pos| !self.asteroids.contains(*pos) && !self.cleared.contains(*pos))
+            .filter_map(|pos| self.board.index(pos))
+            .collect();
+        let inside = area.iter().fold(0.0, |sum, &i| sum + self.belief[i]);
+        if area.is_empty() || inside >= 1.0 {
+            return;
+        }
+        if inside == 0.0 {
+            // the belief ruled it out, so it must have been wrong there
+            for &i in area.iter() {
+                self.belief[i] = 1.0 / area.len() as f64;
+            }
+        } else {
+            for &i in area.iter() {
+                self.belief[i] /= inside;
+            }
+        }
+        // the rest of the enemies are on the open hexes outside the area,
+        // spotted hexes keep their one enemy each
+        let mut in_area = vec![false; self.belief.len()];
+        for &i in area.iter() {
+            in_area[i] = true;
+        }
+        let rest: Vec<usize> = (0..self.belief.len())
+            .filter(|&i| !in_area[i] && !self.spotted.contains(self.board.position(i)))
+            .collect();
+        let outside = (self.enemies as f64 - 1.0 - self.spotted.len() as f64).max(0.0);
+        let outside_before = rest.iter().fold(0.0, |sum, &i| sum + self.belief[i]);
+        for &i in rest.iter() {
+            self.belief[i] = if outside_before > 0.0 { self.belief[i] * outside / outside_before } else { 0.0 };
+        }
+    }
+
+    /// Where a cannon with a blast of `radius` hits the most enemies on
+    /// average, and how many that is.
+    pub fn best_target(&self, radius: u32) -> Option<(Position, f64)> {
+        self.board.hexes().iter()
+            .map(|pos| (*pos, self.belief_within(*pos, radius)))
+            .fold(None, |best, (pos, expected)| {
+                match best {
+                    Some((_, most)) if most >= expected => best,
+                    _ => Some((pos, expected))
+                }
+            })
+    }
+
+    /// Makes the belief agree with what is known for sure: nobody on asteroids
+    /// or cleared hexes, one enemy on each spotted hex, and the rest of the
+    /// enemies spread over the other hexes as before.
+    fn normalize(&mut self) {
+        let spotted = self.spotted.len() as f64;
+        let rest = (self.enemies as f64 - spotted).max(0.0);
+        let mut open = Vec::new();
+        for i in 0..self.belief.len() {
+            let pos = self.board.position(i);
+            if self.spotted.contains(pos) {
+                self.belief[i] = if self.enemies == 0 { 0.0 } else { 1.0 };
+            } else if self.asteroids.contains(pos) || self.cleared.contains(pos) {
+                self.belief[i] = 0.0;
+            } else {
+                open.push(i);
+            }
+        }
+        let total = open.iter().fold(0.0, |sum, &i| sum + self.belief[i]);
+        for &i in open.iter() {
+            self.belief[i] = if total > 0.0 {
+                self.belief[i] * rest / total
+            } else {
+                // everything we believed was ruled out, start over with what's left
+                rest / open.len() as f64
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+fn total(tracker: &Tracker) -> f64 {
+    tracker.board.hexes().iter().fold(0.0, |sum, pos| sum + tracker.belief(*pos))
+}
+
+#[test]
+fn test_clear_and_spot() {
+    let board = Board::with_radius(5);
+    let mut tracker = Tracker::new(&board, 2, 2);
+    assert!((total(&tracker) - 2.0).abs() < 1e-9);
+
+    let origin = Position { x: 0, y: 0 };
+    tracker.add_asteroid(Position { x: 5, y: 0 });
+    tracker.clear_area(origin, 2);
+    assert!(tracker.belief(Position { x: 5, y: 0 }) == 0.0);
+    assert!(tracker.belief_within(origin, 2) == 0.0);
+    assert!((total(&tracker) - 2.0).abs() < 1e-9);
+
+    let enemy = Position { x: 3, y: -1 };
+    tracker.spotted(enemy);
+    assert!(tracker.belief(enemy) == 1.0);
+    assert!(tracker.best_target(0).unwrap().0 == enemy);
+    assert!((total(&tracker) - 2.0).abs() < 1e-9);
+
+    // a round later the enemy could be anywhere within a move, the cleared
+    // hexes are open again
+    tracker.predict();
+    assert!(tracker.belief(enemy) < 1.0);
+    assert!(tracker.belief_within(enemy, 2) > tracker.belief_within(Position { x: -3, y: 1 }, 2));
+    assert!(tracker.belief(Position { x: 1, y: 0 }) > 0.0);
+    assert!(tracker.belief(Position { x: 5, y: 0 }) == 0.0);
+    assert!((total(&tracker) - 2.0).abs() < 1e-9);
+
+    tracker.set_enemies(1);
+    assert!((total(&tracker) - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_hit_area() {
+    let board = Board::with_radius(5);
+    let mut tracker = Tracker::new(&board, 2, 2);
+    let target = Position { x: -2, y: 2 };
+    tracker.hit_area(target, 1);
+    assert!((tracker.belief_within(target, 1) - 1.0).abs() < 1e-9);
+    assert!((total(&tracker) - 2.0).abs() < 1e-9);
+    assert!(tracker.best_target(1).unwrap().0.distance(target) <= 1);
+
+    // a hit far from where the only enemy was last seen still counts
+    let mut tracker = Tracker::new(&board, 1, 1);
+    tracker.spotted(target);
+    tracker.predict();
+    let far = Position { x: 4, y: 0 };
+    assert!(tracker.belief_within(far, 1) == 0.0);
+    tracker.hit_area(far, 1);
+    assert!((tracker.belief_within(far, 1) - 1.0).abs() < 1e-9);
+    assert!(tracker.belief(target) == 0.0);
+}
+
+#[test]
+fn test_hit_keeps_spotted_enemies() {
+    let board = Board::with_radius(5);
+    let mut tracker = Tracker::new(&board, 2, 3);
+    let enemy = Position { x: 4, y: -2 };
+    let target = Position { x: -2, y: 2 };
+    tracker.spotted(enemy);
+    tracker.hit_area(target, 1);
+    assert!(tracker.belief(enemy) == 1.0);
+    assert!((tracker.belief_within(target, 1) - 1.0).abs() < 1e-9);
+    assert!((total(&tracker) - 3.0).abs() < 1e-9);
+    let best = tracker.best_target(0).unwrap();
+    assert!(best.0 == enemy && best.1 == 1.0);
+}
@@ -0,0 +1,257 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::{Position, GameConfig};
+use super::ai::{Action, MoveAction};
+use super::incoming::Team;
+use super::outgoing::action_kind;
+use super::board::Board;
+use super::hexset::HexSet;
+use super::pathfinding;
+use std::collections::HashSet;
+use std::fmt;
+
+/// What to do with actions that the server would reject or ignore.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationMode {
+    /// Only print the violations, send the actions as they are
+    Report,
+    /// Fix or drop offending actions and give idle bots a safe move
+    Repair
+}
+
+impl ValidationMode {
+    pub fn from_name(name: &str) -> Option<ValidationMode> {
+        match name {
+            "report" => Some(ValidationMode::Report),
+            "repair" => Some(ValidationMode::Repair),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    UnknownBot { bot_id: u32 },
+    DeadBot { bot_id: u32 },
+    DuplicateAction { bot_id: u32 },
+    MoveTooFar { bot_id: u32, from: Position, to: Position, max: u32 },
+    OffField { bot_id: u32, kind: &'static str, pos: Position },
+    MissingAction { bot_id: u32 }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::UnknownBot { bot_id } =>
+                write!(f, "bot {} is not ours", bot_id),
+            Violation::DeadBot { bot_id } =>
+                write!(f, "bot {} is dead", bot_id),
+            Violation::DuplicateAction { bot_id } =>
+                write!(f, "bot {} already has an action this round", bot_id),
+            Violation::MoveTooFar { bot_id, from, to, max } =>
+                write!(f, "bot {} can't move from {:?} to {:?}, that is {} steps and the limit is {}",
+                       bot_id, from, to, from.distance(to), max),
+            Violation::OffField { bot_id, kind, pos } =>
+                write!(f, "bot {} targets {:?} with {}, which is outside the field", bot_id, pos, kind),
+            Violation::MissingAction { bot_id } =>
+                write!(f, "bot {} has no action this round", bot_id)
+        }
+    }
+}
+
+/// The legal move destination for a bot at `from` that is closest to `to`.
+fn clamp_move(from: Position, to: Position, config: &GameConfig, board: &Board) -> Position {
+    board.clip(from.positions_within(config.move_)).into_iter()
+        .fold(from, |memo, hex| {
+            if hex.distance(to) < memo.distance(to) { hex } else { memo }
+        })
+}
+
+/// A move as far from where the bot stands as it can get, around the
+/// `asteroids` we know of.
+pub fn safe_move(from: Position, config: &GameConfig, asteroids: &HexSet) -> Position {
+    pathfinding::move_away_from(&Board::new(config), asteroids, from, from, config.move_)
+}
+
+/// Checks the actions an `Ai` produced against the rules before they are sent.
+/// In `Report` mode the actions are returned untouched, in `Repair` mode the
+/// returned actions are legal and every living bot has exactly one of them.
+/// Idle bots are moved around the `asteroids` we know of.
+pub fn validate(actions: Vec<Action>, config: &GameConfig, you: &Team, asteroids: &HexSet, mode: ValidationMode) -> (Vec<Action>, Vec<Violation>) {
+    let board = Board::new(config);
+    let mut violations = Vec::new();
+    let mut repaired = Vec::with_capacity(actions.len());
+    let mut acted: HashSet<u32> = HashSet::new();
+    let originals = actions.clone();
+
+    for action in actions.into_iter() {
+        let (bot_id, pos) = match action {
+            Action::CannonAction(ref a) => (a.bot_id, a.pos),
+            Action::MoveAction(ref a) => (a.bot_id, a.pos),
+            Action::RadarAction(ref a) => (a.bot_id, a.pos)
+        };
+        let bot = match you.bots.iter().find(|bot| bot.bot_id == bot_id) {
+            Some(bot) => bot,
+            None => {
+                violations.push(Violation::UnknownBot { bot_id: bot_id });
+                continue;
+            }
+        };
+        if !bot.alive {
+            violations.push(Violation::DeadBot { bot_id: bot_id });
+            continue;
+        }
+        if !acted.insert(bot_id) {
+            violations.push(Violation::DuplicateAction { bot_id: bot_id });
+            continue;
+        }
+        let action = match action {
+            Action::MoveAction(a) => {
+                let too_far = bot.pos.distance(a.pos) > config.move_ as i32;
+                let off_field = !board.contains(a.pos);
+                if too_far {
+                    violations.push(Violation::MoveTooFar { bot_id: bot_id, from: bot.pos, to: a.pos, max: config.move_ });
+                }
+                if off_field {
+                    violations.push(Violation::OffField { bot_id: bot_id, kind: "move", pos: a.pos });
+                }
+                if too_far || off_field {
+                    Action::MoveAction(MoveAction { bot_id: bot_id, pos: clamp_move(bot.pos, a.pos, config, &board) })
+                }
+                else {
+                    Action::MoveAction(a)
+                }
+            },
+            mut other => {
+                if !board.contains(pos) {
+                    violations.push(Violation::OffField { bot_id: bot_id, kind: action_kind(&other), pos: pos });
+                    let nearest = board.nearest(pos);
+                    match other {
+                        Action::CannonAction(ref mut a) => a.pos = nearest,
+                        Action::RadarAction(ref mut a) => a.pos = nearest,
+                        Action::MoveAction(_) => unreachable!()
+                    }
+                }
+                other
+            }
+        };
+        repaired.push(action);
+    }
+
+    for bot in you.bots.iter().filter(|bot| bot.alive && !acted.contains(&bot.bot_id)) {
+        violations.push(Violation::MissingAction { bot_id: bot.bot_id });
+        repaired.push(Action::MoveAction(MoveAction { bot_id: bot.bot_id, pos: safe_move(bot.pos, config, asteroids) }));
+    }
+
+    match mode {
+        ValidationMode::Report => (originals, violations),
+        ValidationMode::Repair => (repaired, violations)
+    }
+}
+
+#[cfg(test)]
+fn test_team() -> Team {
+    use super::incoming::Bot;
+    let bot = |bot_id: u32, alive: bool, x: i32, y: i32| Bot {
+        bot_id: bot_id, name: format!("Bot {}", bot_id), team_id: 1, hp: 10, alive: alive, pos: Position { x: x, y: y }
+    };
+    Team { name: "Ferrous Bueller".to_string(), team_id: 1, bots: vec![bot(0, true, 0, 0), bot(1, true, 5, 0), bot(2, false, 1, 1)] }
+}
+
+#[test]
+fn test_validate_report_keeps_actions() {
+    let config = GameConfig { field_radius: 5, move_: 2, ..Default::default() };
+    let actions = vec![
+        Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 3, y: 0 } }),
+        Action::MoveAction(MoveAction { bot_id: 1, pos: Position { x: 4, y: 0 } })
+    ];
+    let (sent, violations) = validate(actions.clone(), &config, &test_team(), &HexSet::new(5), ValidationMode::Report);
+    assert!(sent == actions);
+    assert!(violations == vec![Violation::MoveTooFar { bot_id: 0, from: Position { x: 0, y: 0 }, to: Position { x: 3, y: 0 }, max: 2 }]);
+}
+
+#[test]
+fn test_validate_repair() {
+    use super::ai::CannonAction;
+    let config = GameConfig { field_radius: 5, move_: 2, ..Default::default() };
+    let actions = vec![
+        Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 3, y: 0 } }),
+        Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 1, y: 0 } }),
+        Action::CannonAction(CannonAction { bot_id: 2, pos: Position { x: 0, y: 0 } }),
+        Action::CannonAction(CannonAction { bot_id: 9, pos: Position { x: 0, y: 0 } })
+    ];
+    let (sent, violations) = validate(actions, &config, &test_team(), &HexSet::new(5), ValidationMode::Repair);
+    assert!(violations.len() == 5);
+    assert!(violations.contains(&Violation::DuplicateAction { bot_id: 0 }));
+    assert!(violations.contains(&Violation::DeadBot { bot_id: 2 }));
+    assert!(violations.contains(&Violation::UnknownBot { bot_id: 9 }));
+    assert!(violations.contains(&Violation::MissingAction { bot_id: 1 }));
+    assert!(sent.len() == 2);
+    assert!(sent[0] == Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 2, y: 0 } }));
+    match sent[1] {
+        Action::MoveAction(ref a) => {
+            assert!(a.bot_id == 1);
+            assert!(Position { x: 5, y: 0 }.distance(a.pos) == 2);
+            assert!(Board::new(&config).contains(a.pos));
+        },
+        _ => panic!("Expected a safe move for bot 1")
+    }
+}
+
+#[test]
+fn test_safe_move_avoids_asteroids() {
+    let config = GameConfig { field_radius: 5, move_: 2, ..Default::default() };
+    let from = Position { x: 0, y: 0 };
+    let open = safe_move(from, &config, &HexSet::new(5));
+    assert!(from.distance(open) == 2);
+    // an asteroid on the hex it picked sends it elsewhere, asteroids all
+    // around it keep it where it is
+    let mut asteroids = HexSet::new(5);
+    asteroids.insert(open);
+    let next = safe_move(from, &config, &asteroids);
+    assert!(next != open && from.distance(next) == 2);
+    let walled = HexSet::from_positions(5, from.positions_within(1).into_iter().filter(|pos| *pos != from));
+    assert!(safe_move(from, &config, &walled) == from);
+}
+
+#[test]
+fn test_validate_repair_off_field_target() {
+    use super::ai::RadarAction;
+    let config = GameConfig { field_radius: 5, move_: 2, ..Default::default() };
+    let actions = vec![
+        Action::RadarAction(RadarAction { bot_id: 0, pos: Position { x: 8, y: 0 } }),
+        Action::RadarAction(RadarAction { bot_id: 1, pos: Position { x: 0, y: 0 } })
+    ];
+    let (sent, violations) = validate(actions, &config, &test_team(), &HexSet::new(5), ValidationMode::Repair);
+    assert!(violations == vec![Violation::OffField { bot_id: 0, kind: "radar", pos: Position { x: 8, y: 0 } }]);
+    assert!(sent[0] == Action::RadarAction(RadarAction { bot_id: 0, pos: Position { x: 5, y: 0 } }));
+}
+
+#[test]
+fn test_validate_move_off_field() {
+    let config = GameConfig { field_radius: 5, move_: 2, ..Default::default() };
+    // bot 1 is on the edge at (5, 0), one step further is in range but off the field
+    let actions = vec![
+        Action::MoveAction(MoveAction { bot_id: 0, pos: Position { x: 1, y: 0 } }),
+        Action::MoveAction(MoveAction { bot_id: 1, pos: Position { x: 6, y: 0 } })
+    ];
+    let (sent, violations) = validate(actions, &config, &test_team(), &HexSet::new(5), ValidationMode::Repair);
+    assert!(violations == vec![Violation::OffField { bot_id: 1, kind: "move", pos: Position { x: 6, y: 0 } }]);
+    assert!(sent[1] == Action::MoveAction(MoveAction { bot_id: 1, pos: Position { x: 5, y: 0 } }));
+}
@@ -0,0 +1,375 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate time;
+
+use std::cmp::max;
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use super::GameConfig;
+use super::ai::{Ai, Action, MoveAction};
+use super::incoming::{Event, EventsMessage, StartMessage, EndMessage};
+use super::hexset::HexSet;
+use super::validation;
+
+#[cfg(test)] use std::sync::{Arc, Mutex};
+
+enum Job {
+    Connected(u32, GameConfig),
+    Start(StartMessage),
+    Round(EventsMessage, u64, u64),
+    End(EndMessage),
+    Sent(u32, Vec<Action>),
+    Replace(Box<Ai + Send>)
+}
+
+enum Reply {
+    Actions { ticket: u64, actions: Vec<Action>, elapsed_ms: u32 },
+    Timeout { ticket: u64 }
+}
+
+/// How often and by how much an AI went over its time budget.
+#[derive(Debug, Default, Clone)]
+pub struct OverrunStats {
+    pub rounds: u32,
+    pub overruns: u32,
+    pub total_over_ms: u64,
+    pub worst_over_ms: u32
+}
+
+impl OverrunStats {
+    fn record(&mut self, over_ms: u32) {
+        self.total_over_ms += over_ms as u64;
+        self.worst_over_ms = max(self.worst_over_ms, over_ms);
+    }
+}
+
+/// How long `report` waits for the answer to a round that went over budget
+/// at the end of the game.
+const LATE_ANSWER_WAIT_MS: u32 = 1000;
+
+/// Runs an `Ai` on its own thread so that a slow `respond` can't make us miss
+/// a round. If the AI doesn't answer within the budget, a cheap fallback is
+/// used instead and the AI's late answer is only used for the statistics.
+pub struct Watchdog {
+    jobs: Sender<Job>,
+    replies: Receiver<Reply>,
+    // ticket and deadline in ns of the rounds the timer thread should time out
+    timer: Sender<(u64, u64)>,
+    budget_ms: u32,
+    // round ids repeat after a reconnect, so replies are matched on this instead
+    next_ticket: u64,
+    // the last round that went over budget and hasn't answered yet
+    late: Option<u64>,
+    pub stats: OverrunStats
+}
+
+impl Watchdog {
+    pub fn new(ai: Box<Ai + Send>) -> Watchdog {
+        let (jobs, job_receiver) = channel();
+        let (reply_sender, replies) = channel();
+        let (timer, timer_receiver) = channel();
+        let worker_replies = reply_sender.clone();
+        thread::spawn(move || run_worker(ai, job_receiver, worker_replies));
+        thread::spawn(move || run_timer(timer_receiver, reply_sender));
+        Watchdog {
+            jobs: jobs,
+            replies: replies,
+            timer: timer,
+            budget_ms: 0,
+            next_ticket: 0,
+            late: None,
+            stats: Default::default()
+        }
+    }
+
+    /// The budget is the server's `loop_time` minus `margin_ms` for the
+    /// round trip, but never less than a tenth of `loop_time`.
+    pub fn set_budget(&mut self, loop_time: i32, margin_ms: u32) {
+        let loop_time = max(loop_time, 0) as u32;
+        self.budget_ms = max(loop_time.saturating_sub(margin_ms), loop_time / 10);
+    }
+
+    pub fn replace(&mut self, ai: Box<Ai + Send>) {
+        let _ = self.jobs.send(Job::Replace(ai));
+    }
+
+    pub fn on_connected(&mut self, team_id: u32, config: GameConfig) {
+        let _ = self.jobs.send(Job::Connected(team_id, config));
+    }
+
+    pub fn on_game_start(&mut self, start: StartMessage) {
+        let _ = self.jobs.send(Job::Start(start));
+    }
+
+    pub fn on_game_end(&mut self, end: EndMessage) {
+        let _ = self.jobs.send(Job::End(end));
+    }
+
+    pub fn on_actions_sent(&mut self, round_id: u32, actions: Vec<Action>) {
+        let _ = self.jobs.send(Job::Sent(round_id, actions));
+    }
+
+    /// Hands the round to the AI and waits at most the budget for its actions.
+    /// The fallback moves go around the `asteroids` we know of.
+    pub fn respond(&mut self, msg: EventsMessage, asteroids: &HexSet) -> Vec<Action> {
+        let round_id = msg.round_id;
+        let fallback: Vec<Action> = msg.you.bots.iter().filter(|bot| bot.alive).map(|bot| {
+            Action::MoveAction(MoveAction { bot_id: bot.bot_id, pos: validation::safe_move(bot.pos, &msg.config, asteroids) })
+        }).collect();
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.stats.rounds += 1;
+        let started_ns = time::precise_time_ns();
+        if self.jobs.send(Job::Round(msg, started_ns, ticket)).is_err() {
+            println!("Round {}: the AI has crashed, using fallback actions", round_id);
+            return fallback;
+        }
+        let _ = self.timer.send((ticket, started_ns + self.budget_ms as u64 * 1000000));
+
+        loop {
+            match self.replies.recv() {
+                Ok(Reply::Actions { ticket: id, actions, elapsed_ms }) => {
+                    self.record_answer(id, elapsed_ms);
+                    if id == ticket {
+                        return actions;
+                    }
+                },
+                Ok(Reply::Timeout { ticket: id }) => {
+                    if id == ticket {
+                        self.stats.overruns += 1;
+                        self.late = Some(ticket);
+                        println!("Round {}: the AI didn't answer within {} ms, using fallback actions", round_id, self.budget_ms);
+                        return fallback;
+                    }
+                },
+                Err(_) => return fallback
+            }
+        }
+    }
+
+    fn record_answer(&mut self, ticket: u64, elapsed_ms: u32) {
+        if elapsed_ms > self.budget_ms {
+            self.stats.record(elapsed_ms - self.budget_ms);
+        }
+        // an answer to a newer round means the late one was answered or skipped
+        if self.late.map_or(false, |late| late <= ticket) {
+            self.late = None;
+        }
+    }
+
+    /// Waits a while for the answer to the last round if it was late, so that
+    /// it is counted too.
+    fn collect_late_answer(&mut self) {
+        let ticket = match self.late {
+            Some(ticket) => ticket,
+            None => return
+        };
+        let _ = self.timer.send((ticket, time::precise_time_ns() + LATE_ANSWER_WAIT_MS as u64 * 1000000));
+        loop {
+            match self.replies.recv() {
+                Ok(Reply::Actions { ticket: id, elapsed_ms, .. }) => {
+                    self.record_answer(id, elapsed_ms);
+                    if id == ticket {
+                        return;
+                    }
+                },
+                Ok(Reply::Timeout { ticket: id }) => {
+                    if id == ticket {
+                        // still no answer, it was over by at least this much
+                        self.stats.record(LATE_ANSWER_WAIT_MS);
+                        self.late = None;
+                        return;
+                    }
+                },
+                Err(_) => return
+            }
+        }
+    }
+
+    pub fn report(&mut self, ai_name: &str) {
+        self.collect_late_answer();
+        let stats = &self.stats;
+        if stats.overruns == 0 {
+            println!("{} answered in time on all {} rounds", ai_name, stats.rounds);
+        }
+        else {
+            println!("{} went over its {} ms budget on {}/{} rounds, worst by {} ms, {} ms in total",
+                     ai_name, self.budget_ms, stats.overruns, stats.rounds, stats.worst_over_ms, stats.total_over_ms);
+        }
+    }
+}
+
+/// Sends a timeout for every ticket once its deadline has passed. Deadlines
+/// come in the order the rounds were sent, so one thread is enough.
+fn run_timer(requests: Receiver<(u64, u64)>, replies: Sender<Reply>) {
+    for (ticket, deadline_ns) in requests.iter() {
+        let now = time::precise_time_ns();
+        if deadline_ns > now {
+            thread::sleep(Duration::from_millis((deadline_ns - now) / 1000000));
+        }
+        if replies.send(Reply::Timeout { ticket: ticket }).is_err() {
+            return;
+        }
+    }
+}
+
+fn is_round(job: &Job) -> bool {
+    match *job {
+        Job::Round(..) => true,
+        _ => false
+    }
+}
+
+fn run_worker(mut ai: Box<Ai + Send>, jobs: Receiver<Job>, replies: Sender<Reply>) {
+    let mut pending = VecDeque::new();
+    // events of the rounds that were skipped, the AI gets them with the next one
+    let mut skipped_events: Vec<Event> = Vec::new();
+    loop {
+        let job = match pending.pop_front() {
+            Some(job) => job,
+            None => match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => return
+            }
+        };
+        match job {
+            Job::Connected(team_id, config) => ai.on_connected(team_id, config),
+            Job::Start(start) => ai.on_game_start(start),
+            Job::End(end) => ai.on_game_end(end),
+            Job::Sent(round_id, actions) => ai.on_actions_sent(round_id, &actions),
+            Job::Replace(new_ai) => ai = new_ai,
+            Job::Round(msg, started_ns, ticket) => {
+                // an AI that fell behind skips the rounds that already got a
+                // fallback and have a newer round waiting behind them, but
+                // still hears what happened in them
+                while let Ok(job) = jobs.try_recv() {
+                    pending.push_back(job);
+                }
+                skipped_events.extend(msg.events.into_iter());
+                if pending.iter().any(is_round) {
+                    continue;
+                }
+                let events = ::std::mem::replace(&mut skipped_events, Vec::new());
+                ai.on_round(msg.round_id);
+                ai.set_state(msg.config, msg.you, msg.other_teams);
+                let actions = ai.respond(events);
+                let elapsed_ms = ((time::precise_time_ns() - started_ns) / 1000000) as u32;
+                if replies.send(Reply::Actions { ticket: ticket, actions: actions, elapsed_ms: elapsed_ms }).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Answers only once the test lets it: a round waits until the sender
+/// returned by `new` is dropped. Records how many events each round had.
+#[cfg(test)]
+struct GatedAi {
+    gate: Receiver<()>,
+    rounds: Arc<Mutex<Vec<usize>>>
+}
+
+#[cfg(test)]
+impl GatedAi {
+    fn new() -> (GatedAi, Sender<()>) {
+        let (open, gate) = channel();
+        (GatedAi { gate: gate, rounds: Arc::new(Mutex::new(Vec::new())) }, open)
+    }
+}
+
+#[cfg(test)]
+impl Ai for GatedAi {
+    fn respond(&mut self, events: Vec<Event>) -> Vec<Action> {
+        self.rounds.lock().unwrap().push(events.len());
+        let _ = self.gate.recv();
+        vec![Action::MoveAction(MoveAction { bot_id: 0, pos: ::Position { x: 0, y: 0 } })]
+    }
+    fn set_state(&mut self, _config: GameConfig, _you: ::incoming::Team, _other_teams: Vec<::incoming::TeamNoPosNoHp>) {}
+    fn get_bot_by_id(&mut self, _bot_id: u32) -> Option<&::incoming::Bot> { None }
+    fn is_on_playing_field(&self, _pos: &::Position) -> bool { true }
+}
+
+#[cfg(test)]
+fn events_message() -> EventsMessage {
+    match ::incoming::parse_message(include_str!("../incoming/fixtures/events.json")).unwrap() {
+        ::incoming::Message::EventsMessage(msg) => msg,
+        _ => panic!("Fixture isn't an events message")
+    }
+}
+
+#[test]
+fn test_watchdog_uses_ai_actions_in_time() {
+    let (ai, open) = GatedAi::new();
+    drop(open);
+    let mut watchdog = Watchdog::new(Box::new(ai));
+    watchdog.set_budget(1000, 50);
+    let actions = watchdog.respond(events_message(), &HexSet::new(0));
+    assert!(actions == vec![Action::MoveAction(MoveAction { bot_id: 0, pos: ::Position { x: 0, y: 0 } })]);
+    assert!(watchdog.stats.rounds == 1 && watchdog.stats.overruns == 0);
+}
+
+#[test]
+fn test_watchdog_falls_back_on_overrun() {
+    let (ai, open) = GatedAi::new();
+    let mut watchdog = Watchdog::new(Box::new(ai));
+    watchdog.set_budget(10, 0);
+    let actions = watchdog.respond(events_message(), &HexSet::new(0));
+    // bots 0 and 2 are alive in the fixture, both get a fallback move
+    assert!(actions.len() == 2);
+    assert!(watchdog.stats.overruns == 1);
+    assert!(watchdog.late == Some(0));
+    // the late answer to the last round is waited for before reporting
+    drop(open);
+    watchdog.report("gated");
+    assert!(watchdog.late.is_none());
+    assert!(watchdog.stats.worst_over_ms < LATE_ANSWER_WAIT_MS);
+}
+
+#[test]
+fn test_watchdog_skips_stale_rounds() {
+    let (ai, open) = GatedAi::new();
+    let rounds = ai.rounds.clone();
+    let mut watchdog = Watchdog::new(Box::new(ai));
+    watchdog.set_budget(10, 0);
+    // the AI is stuck in the first round while the other three come in
+    for _ in 0..4 {
+        watchdog.respond(events_message(), &HexSet::new(0));
+    }
+    drop(open);
+    watchdog.report("gated");
+    // only the newest round is answered, with the events of the two skipped
+    // ones in front of its own
+    let per_round = events_message().events.len();
+    assert!(*rounds.lock().unwrap() == vec![per_round, 3 * per_round]);
+    assert!(watchdog.stats.rounds == 4 && watchdog.stats.overruns == 4);
+}
+
+#[test]
+fn test_budget_keeps_a_minimum() {
+    let (ai, _open) = GatedAi::new();
+    let mut watchdog = Watchdog::new(Box::new(ai));
+    watchdog.set_budget(300, 50);
+    assert!(watchdog.budget_ms == 250);
+    watchdog.set_budget(40, 50);
+    assert!(watchdog.budget_ms == 4);
+}
@@ -0,0 +1,257 @@
+/** Rust Tyckiting client - A websocket client for a fight to kill all other bots
+ *  Copyright Futurice Oy (2015)
+ *
+ *  This file is part of Rust Tyckiting client.
+ *
+ *  Rust Tyckiting client is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  Rust Tyckiting client is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with Rust Tyckiting client.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fmt;
+use std::io::Read;
+
+use websocket::client::request::Url;
+
+use serde::json;
+
+use hyper::Client as HClient;
+use hyper::header::Authorization;
+use hyper::status::StatusCode;
+
+use rustc_serialize::base64::{ToBase64, MIME};
+
+use super::session::GameOutcome;
+
+#[derive(Serialize)]
+struct WebGameRequest {
+    bots: Vec<String>
+}
+
+#[derive(Deserialize)]
+struct WebGameResponse {
+    #[allow(dead_code)] status: String,
+    #[serde(rename="gameId")] game_id: u32,
+    host: String,
+    port: u32
+}
+
+/// The account used for the web API, never taken from the command line.
+pub struct Credentials {
+    pub user: String,
+    pub password: String
+}
+
+/// A game the web API has set up for us.
+#[derive(Debug, PartialEq)]
+pub struct WebGame {
+    pub game_id: u32,
+    pub host: String,
+    pub port: u32
+}
+
+/// Everything that can go wrong while asking for a web game.
+#[derive(Debug)]
+pub enum WebGameError {
+    MissingCredentials,
+    BadApiUrl(String),
+    Unreachable { url: String, reason: String },
+    AuthFailed { user: String },
+    UnknownOpponent { opponents: Vec<String>, body: String },
+    UnexpectedStatus { status: String, body: String },
+    BadResponse { body: String, reason: String }
+}
+
+impl fmt::Display for WebGameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WebGameError::MissingCredentials =>
+                write!(f, "no credentials for the web API, set TYCKITING_USER and TYCKITING_PASSWORD or user and password in the config file"),
+            WebGameError::BadApiUrl(ref url) =>
+                write!(f, "{} isn't a valid API URL", url),
+            WebGameError::Unreachable { ref url, ref reason } =>
+                write!(f, "couldn't reach {}: {}", url, reason),
+            WebGameError::AuthFailed { ref user } =>
+                write!(f, "the server didn't accept the password for {}", user),
+            WebGameError::UnknownOpponent { ref opponents, ref body } =>
+                write!(f, "the server refused to start a game against {}, check the names: {}", opponents.connect(", "), body),
+            WebGameError::UnexpectedStatus { ref status, ref body } =>
+                write!(f, "the server answered {}: {}", status, body),
+            WebGameError::BadResponse { ref body, ref reason } =>
+                write!(f, "couldn't understand the server's answer ({}): {}", reason, body)
+        }
+    }
+}
+
+/// The API endpoint used when none is configured.
+pub fn default_api_url(host: &str) -> String {
+    format!("http://{}/api/new", host)
+}
+
+/// Asks the web API at `api_url` for a game against `opponents`.
+pub fn request_game(api_url: &str, credentials: &Credentials, opponents: &[String]) -> Result<WebGame, WebGameError> {
+    let url = try!(Url::parse(api_url).map_err(|_| WebGameError::BadApiUrl(api_url.to_string())));
+    let auth = format!("{}:{}", credentials.user, credentials.password).as_bytes().to_base64(MIME);
+    let request_body = json::to_string(&WebGameRequest { bots: opponents.to_vec() }).unwrap();
+
+    let mut client = HClient::new();
+    let mut response = try!(client.post(url)
+        .header(Authorization(format!("Basic {}", auth)))
+        .body(&request_body[..])
+        .send()
+        .map_err(|e| WebGameError::Unreachable { url: api_url.to_string(), reason: e.to_string() }));
+    let mut body = String::new();
+    try!(response.read_to_string(&mut body)
+         .map_err(|e| WebGameError::Unreachable { url: api_url.to_string(), reason: e.to_string() }));
+
+    match response.status {
+        StatusCode::Ok => (),
+        StatusCode::Unauthorized | StatusCode::Forbidden =>
+            return Err(WebGameError::AuthFailed { user: credentials.user.clone() }),
+        StatusCode::BadRequest | StatusCode::NotFound | StatusCode::UnprocessableEntity =>
+            return Err(WebGameError::UnknownOpponent { opponents: opponents.to_vec(), body: body }),
+        status =>
+            return Err(WebGameError::UnexpectedStatus { status: status.to_string(), body: body })
+    }
+    let game = try!(json::from_str::<WebGameResponse>(&body)
+                    .map_err(|e| WebGameError::BadResponse { reason: e.to_string(), body: body.clone() }));
+    Ok(WebGame { game_id: game.game_id, host: game.host, port: game.port })
+}
+
+/// The opponent lists to request games with: all of `opponents` in one game,
+/// or with `each` a separate game against every one of them, `repeat` times over.
+pub fn plan_batch(opponents: &[String], each: bool, repeat: u32) -> Vec<Vec<String>> {
+    let games: Vec<Vec<String>> = if each {
+        opponents.iter().map(|opponent| vec![opponent.clone()]).collect()
+    }
+    else {
+        vec![opponents.to_vec()]
+    };
+    (0..repeat).flat_map(|_| games.clone().into_iter()).collect()
+}
+
+/// One game of a batch: the game id if the API gave us one, and how the game
+/// went or why it couldn't be played.
+pub struct BatchResult {
+    pub opponents: Vec<String>,
+    pub game_id: Option<u32>,
+    pub outcome: Result<GameOutcome, String>
+}
+
+pub fn print_summary(results: &[BatchResult]) {
+    println!("{:>6}  {:<30} {}", "Game", "Opponents", "Result");
+    for result in results.iter() {
+        let game_id = result.game_id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string());
+        let outcome = match result.outcome {
+            Ok(ref outcome) if outcome.won() => "won".to_string(),
+            Ok(ref outcome) => match outcome.winner_name {
+                Some(ref winner) => format!("lost, {} won", winner),
+                None if outcome.winner_team_id.is_some() => "lost".to_string(),
+                None => "no winner".to_string()
+            },
+            Err(ref reason) => format!("not played: {}", reason)
+        };
+        println!("{:>6}  {:<30} {}", game_id, result.opponents.connect(", "), outcome);
+    }
+    let won = results.iter().filter(|r| r.outcome.as_ref().map(|o| o.won()).unwrap_or(false)).count();
+    let played = results.iter().filter(|r| r.outcome.is_ok()).count();
+    println!("Won {} of {} games played, {} requested", won, played, results.len());
+}
+
+/// Answers a single HTTP request on a local port with `status_line` and
+/// `body`. Returns the URL to request and a handle that yields the request.
+#[cfg(test)]
+fn mock_api(status_line: &'static str, body: &'static str) -> (String, ::std::thread::JoinHandle<String>) {
+    use std::io::Write;
+    use std::net::TcpListener;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api/new", listener.local_addr().unwrap());
+    let handle = ::std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        loop {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend(buffer[..read].iter().cloned());
+            let text = String::from_utf8_lossy(&request).into_owned();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text.lines()
+                    .find(|line| line.to_lowercase().starts_with("content-length:"))
+                    .and_then(|line| line[15..].trim().parse().ok())
+                    .unwrap_or(0);
+                if read == 0 || request.len() >= end + 4 + length {
+                    break;
+                }
+            }
+            if read == 0 {
+                break;
+            }
+        }
+        write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status_line, body.len(), body).unwrap();
+        String::from_utf8_lossy(&request).into_owned()
+    });
+    (url, handle)
+}
+
+#[cfg(test)]
+fn test_credentials() -> Credentials {
+    Credentials { user: "user".to_string(), password: "secret".to_string() }
+}
+
+#[test]
+fn test_request_game() {
+    let (url, handle) = mock_api("200 OK", r#"{"status":"ok","gameId":12,"host":"game.example","port":3012}"#);
+    let game = request_game(&url, &test_credentials(), &["Rooney".to_string()]).unwrap();
+    assert!(game == WebGame { game_id: 12, host: "game.example".to_string(), port: 3012 });
+    let request = handle.join().unwrap();
+    assert!(request.starts_with("POST /api/new"));
+    assert!(request.contains(&format!("Basic {}", "user:secret".as_bytes().to_base64(MIME))));
+    assert!(request.ends_with(r#"{"bots":["Rooney"]}"#));
+}
+
+#[test]
+fn test_request_game_errors() {
+    let opponents = vec!["Nobody".to_string()];
+    let (url, _) = mock_api("401 Unauthorized", "");
+    match request_game(&url, &test_credentials(), &opponents) {
+        Err(WebGameError::AuthFailed { user }) => assert!(user == "user"),
+        other => panic!("Expected an auth failure, got {:?}", other)
+    }
+    let (url, _) = mock_api("404 Not Found", "no such bot");
+    match request_game(&url, &test_credentials(), &opponents) {
+        Err(WebGameError::UnknownOpponent { body, .. }) => assert!(body == "no such bot"),
+        other => panic!("Expected an unknown opponent, got {:?}", other)
+    }
+    let (url, _) = mock_api("200 OK", "<html>maintenance</html>");
+    match request_game(&url, &test_credentials(), &opponents) {
+        Err(WebGameError::BadResponse { .. }) => (),
+        other => panic!("Expected a bad response, got {:?}", other)
+    }
+    // nothing listens on a port that was just released
+    let url = {
+        let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/api/new", listener.local_addr().unwrap())
+    };
+    match request_game(&url, &test_credentials(), &opponents) {
+        Err(WebGameError::Unreachable { .. }) => (),
+        other => panic!("Expected an unreachable host, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_plan_batch() {
+    let opponents = vec!["Rooney".to_string(), "Grace".to_string()];
+    assert!(plan_batch(&opponents, false, 1) == vec![opponents.clone()]);
+    let each = plan_batch(&opponents, true, 3);
+    assert!(each.len() == 6);
+    assert!(each[0] == vec!["Rooney".to_string()] && each[1] == vec!["Grace".to_string()] && each[2] == each[0]);
+    assert!(plan_batch(&opponents, true, 0).is_empty());
+}